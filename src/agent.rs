@@ -0,0 +1,455 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! The unlock agent, a daemon that keeps the unlocked databases open.
+//!
+//! `yor unlock` hands the key derived from the password of a database to the
+//! agent and the following commands ask the agent before prompting, like
+//! ssh-agent. The password itself never leaves the process it was typed in,
+//! and the commands don't run the key derivation again. The keys are kept in
+//! locked memory (never swapped out) and wiped when the database is locked,
+//! or when the agent exits after being idle for its timeout.
+//!
+//! The agent listens on `$XDG_RUNTIME_DIR/yor/agent.sock` (or
+//! `$YOR_AGENT_SOCK`), the directory is only accessible by the user. Each
+//! connection is a single request and response, one json document per line.
+
+use crate::error::YorError;
+use crate::yor::KdfParams;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+/// The idle time after which the agent exits, in seconds
+pub const DEFAULT_TIMEOUT: u64 = 15 * 60;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    /// The key of the given database derived with the salt and parameters
+    Get {
+        db: PathBuf,
+        salt: Vec<u8>,
+        kdf: KdfParams,
+    },
+    /// Keep the key of the given database, in place of the previous one
+    Add {
+        db: PathBuf,
+        salt: Vec<u8>,
+        kdf: KdfParams,
+        key: Vec<u8>,
+    },
+    /// Forget the key of the given database, or all of them
+    Lock {
+        db: Option<PathBuf>,
+    },
+    Status,
+    Stop,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Ok,
+    Key { key: Option<Vec<u8>> },
+    Locked { count: usize },
+    Status(AgentStatus),
+}
+
+/// The state of a running agent
+#[derive(Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub pid: u32,
+    /// The idle time after which the agent exits, in seconds
+    pub timeout: u64,
+    /// The unlocked databases
+    pub databases: Vec<PathBuf>,
+}
+
+/// A secret kept in memory that can't be swapped out, it is wiped when
+/// dropped
+struct LockedSecret {
+    bytes: Box<[u8]>,
+}
+
+impl LockedSecret {
+    fn new(secret: &[u8]) -> LockedSecret {
+        let bytes: Box<[u8]> = secret.into();
+        #[cfg(unix)]
+        if !bytes.is_empty() {
+            // Best effort, the limit of locked memory may be reached
+            unsafe { libc::mlock(bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        }
+        LockedSecret { bytes }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for LockedSecret {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+        #[cfg(unix)]
+        if !self.bytes.is_empty() {
+            unsafe { libc::munlock(self.bytes.as_ptr() as *const libc::c_void, self.bytes.len()) };
+        }
+    }
+}
+
+/// The path of the socket of the agent: `$YOR_AGENT_SOCK`, or `agent.sock`
+/// in the `yor` directory of `$XDG_RUNTIME_DIR`
+pub fn socket_path() -> Result<PathBuf, YorError> {
+    if let Some(path) = std::env::var_os("YOR_AGENT_SOCK") {
+        return Ok(PathBuf::from(path));
+    }
+    dirs::runtime_dir()
+        .map(|dir| dir.join("yor").join("agent.sock"))
+        .ok_or_else(|| {
+            YorError::Invalid(String::from(
+                "The agent needs $XDG_RUNTIME_DIR (or $YOR_AGENT_SOCK) to be set.",
+            ))
+        })
+}
+
+/// The agent identifies the databases by their absolute path
+fn db_id(db: &Path) -> PathBuf {
+    std::fs::canonicalize(db).unwrap_or_else(|_| db.to_path_buf())
+}
+
+/// Check if an agent is listening on the socket
+pub fn is_running() -> bool {
+    status().is_ok()
+}
+
+/// Get the state of the running agent
+pub fn status() -> Result<AgentStatus, YorError> {
+    match request(&Request::Status)? {
+        Response::Status(status) => Ok(status),
+        _ => Err(unexpected()),
+    }
+}
+
+/// Get the key of the given database from the agent, derived with the given
+/// salt and parameters. Returns `None` if the agent isn't running, the
+/// database isn't unlocked or its key was derived with other ones (a stale
+/// key after a rekey). The caller still has to check the key.
+pub fn get(db: &Path, salt: &[u8], kdf: &KdfParams) -> Option<Zeroizing<Vec<u8>>> {
+    let req = Request::Get {
+        db: db_id(db),
+        salt: salt.to_vec(),
+        kdf: *kdf,
+    };
+    match request(&req) {
+        Ok(Response::Key { key }) => key.map(Zeroizing::new),
+        _ => None,
+    }
+}
+
+/// Hand the key of the given database to the agent
+///
+/// # Arguments
+/// - `db` - The database
+/// - `salt` - The salt the key is derived with
+/// - `kdf` - The KDF parameters the key is derived with
+/// - `key` - The database key
+pub fn add(db: &Path, salt: &[u8], kdf: &KdfParams, key: &[u8]) -> Result<(), YorError> {
+    let mut req = Request::Add {
+        db: db_id(db),
+        salt: salt.to_vec(),
+        kdf: *kdf,
+        key: key.to_vec(),
+    };
+    let response = request(&req);
+    if let Request::Add { key, .. } = &mut req {
+        key.zeroize();
+    }
+    match response? {
+        Response::Ok => Ok(()),
+        _ => Err(unexpected()),
+    }
+}
+
+/// Make the agent forget the key of the given database, or of every
+/// database. Returns the number of databases locked.
+pub fn lock(db: Option<&Path>) -> Result<usize, YorError> {
+    match request(&Request::Lock { db: db.map(db_id) })? {
+        Response::Locked { count } => Ok(count),
+        _ => Err(unexpected()),
+    }
+}
+
+/// Wipe the keys and stop the agent
+pub fn stop() -> Result<(), YorError> {
+    match request(&Request::Stop)? {
+        Response::Ok => Ok(()),
+        _ => Err(unexpected()),
+    }
+}
+
+fn unexpected() -> YorError {
+    YorError::Other(String::from("Unexpected response from the agent."))
+}
+
+#[cfg(unix)]
+fn request(request: &Request) -> Result<Response, YorError> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|_| YorError::NotFound(String::from("The agent is not running.")))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    let sent = stream.write_all(&line);
+    line.zeroize();
+    sent?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let response = serde_json::from_str(&line);
+    line.zeroize();
+    Ok(response?)
+}
+
+#[cfg(not(unix))]
+fn request(_request: &Request) -> Result<Response, YorError> {
+    Err(YorError::Invalid(String::from(
+        "The agent needs Unix sockets.",
+    )))
+}
+
+/// The keys kept by the agent
+#[cfg(unix)]
+struct State {
+    /// The key of each database with its salt and KDF parameters
+    secrets: std::collections::BTreeMap<PathBuf, (Vec<u8>, KdfParams, LockedSecret)>,
+    /// The last request, `Status` doesn't count
+    last_used: std::time::Instant,
+}
+
+/// The uid of the process on the other end of the connection
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let found = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    } == 0;
+    found.then_some(cred.uid)
+}
+
+/// The uid of the process on the other end of the connection
+#[cfg(all(unix, not(target_os = "linux")))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+    let (mut uid, mut gid) = (0, 0);
+    let found = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == 0;
+    found.then_some(uid)
+}
+
+/// Check that the directory of the socket is only accessible by the user,
+/// it may have been created by someone else
+#[cfg(unix)]
+fn check_socket_dir(dir: &Path) -> Result<(), YorError> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(dir)?;
+    if meta.uid() != unsafe { libc::getuid() } || meta.mode() & 0o077 != 0 {
+        return Err(YorError::Invalid(format!(
+            "The directory of the agent socket must belong to the user with mode 700: {}",
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Answer the request of the connection. Returns `true` if the agent has to
+/// stop.
+#[cfg(unix)]
+fn handle(
+    mut stream: std::os::unix::net::UnixStream,
+    state: &std::sync::Mutex<State>,
+    timeout: u64,
+) -> bool {
+    use std::io::{BufRead, BufReader, Write};
+    use std::time::{Duration, Instant};
+
+    // Only the processes of the user
+    if peer_uid(&stream) != Some(unsafe { libc::getuid() }) {
+        return false;
+    }
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+    let mut line = String::new();
+    let read = BufReader::new(&stream).read_line(&mut line);
+    let request = read.ok().and_then(|_| serde_json::from_str(&line).ok());
+    line.zeroize();
+    let mut request: Request = match request {
+        Some(request) => request,
+        None => return false,
+    };
+
+    let mut state = state.lock().unwrap();
+    // Checking if the agent runs doesn't keep it alive
+    if !matches!(request, Request::Status) {
+        state.last_used = Instant::now();
+    }
+    let response = match &mut request {
+        Request::Get { db, salt, kdf } => Response::Key {
+            key: state
+                .secrets
+                .get(db)
+                .filter(|(s, p, _)| s == salt && p == kdf)
+                .map(|(_, _, key)| key.bytes().to_vec()),
+        },
+        Request::Add { db, salt, kdf, key } => {
+            let secret = (salt.clone(), *kdf, LockedSecret::new(key));
+            state.secrets.insert(db.clone(), secret);
+            key.zeroize();
+            Response::Ok
+        }
+        Request::Lock { db: Some(db) } => Response::Locked {
+            count: state.secrets.remove(db).map_or(0, |_| 1),
+        },
+        Request::Lock { db: None } => {
+            let count = state.secrets.len();
+            state.secrets.clear();
+            Response::Locked { count }
+        }
+        Request::Status => Response::Status(AgentStatus {
+            pid: std::process::id(),
+            timeout,
+            databases: state.secrets.keys().cloned().collect(),
+        }),
+        Request::Stop => Response::Ok,
+    };
+    drop(state);
+
+    if let Ok(mut line) = serde_json::to_vec(&response) {
+        line.push(b'\n');
+        let _ = stream.write_all(&line);
+        line.zeroize();
+    }
+    if let Response::Key { key: Some(mut key) } = response {
+        key.zeroize();
+    }
+    matches!(request, Request::Stop)
+}
+
+/// Run the agent in the foreground until it is stopped or idle for the
+/// given number of seconds. Each connection is answered on its own thread.
+#[cfg(unix)]
+pub fn serve(timeout: u64) -> Result<(), YorError> {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    let path = socket_path()?;
+    if is_running() {
+        return Err(YorError::Invalid(format!(
+            "An agent is already running on: {}",
+            path.display()
+        )));
+    }
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        check_socket_dir(dir)?;
+    }
+    // A socket left by an agent that didn't exit cleanly
+    let _ = fs::remove_file(&path);
+    // The socket is created with mode 600, not changed after
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+
+    // Other processes of the user can't read the memory of the agent
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+
+    let state = Arc::new(Mutex::new(State {
+        secrets: BTreeMap::new(),
+        last_used: Instant::now(),
+    }));
+    let shutdown = {
+        let path = path.clone();
+        move |state: &Mutex<State>| {
+            state.lock().unwrap().secrets.clear();
+            let _ = fs::remove_file(&path);
+            std::process::exit(0);
+        }
+    };
+
+    {
+        let state = Arc::clone(&state);
+        let shutdown = shutdown.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(1));
+            let idle = state.lock().unwrap().last_used.elapsed();
+            if idle >= Duration::from_secs(timeout) {
+                shutdown(&state);
+            }
+        });
+    }
+
+    // A client that doesn't send its request doesn't hold up the others
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let state = Arc::clone(&state);
+        let shutdown = shutdown.clone();
+        std::thread::spawn(move || {
+            if handle(stream, &state, timeout) {
+                shutdown(&state);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_timeout: u64) -> Result<(), YorError> {
+    Err(YorError::Invalid(String::from(
+        "The agent needs Unix sockets.",
+    )))
+}
@@ -17,7 +17,8 @@
  *
 */
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -29,8 +30,41 @@ use clap::{Args, Parser, Subcommand};
 pub struct YorParser {
     #[clap(subcommand)]
     pub command: Op,
+
+    /// Read the passwords from stdin, one per line
+    #[clap(long, global = true, conflicts_with = "password-file")]
+    pub password_stdin: bool,
+
+    /// Read the passwords from the given file, one per line
+    #[clap(long, global = true, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+
+    /// The directory of the vault (default: $YOR_HOME or $XDG_DATA_HOME/yor)
+    #[clap(long, global = true, value_name = "PATH")]
+    pub home: Option<PathBuf>,
+
+    /// The output format of ls, ls-db, ls-file and get (default: table on a
+    /// terminal, plain otherwise)
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Same as --format json, the errors are printed as json on stderr too
+    #[clap(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Colored output for humans
+    Table,
+    /// One record per line, tab separated fields and no colors
+    Plain,
+    /// A json document
+    Json,
 }
 
+// Parsed once, the size of the variants doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 pub enum Op {
     #[clap(about = "Information about the app.")]
@@ -39,9 +73,23 @@ pub enum Op {
     LsDb,
     #[clap(about = "List all files avaialable from the file environment")]
     LsFile,
-    #[clap(about = "Load all keys from the current database into environment variable")]
-    LoadEnv,
+    Init(InitCommand),
+    LoadEnv(LoadEnvCommand),
+    Exec(ExecCommand),
+    Config(ConfigCommand),
+    Rekey(RekeyCommand),
+    Agent(AgentCommand),
+    Unlock(UnlockCommand),
+    Lock(LockCommand),
+    #[clap(about = "Remove the expired keys of every database and purge the trash")]
+    Gc,
+    Trash(TrashCommand),
     Set(SetCommand),
+    Gen(GenCommand),
+    Totp(TotpCommand),
+    Meta(MetaCommand),
+    History(HistoryCommand),
+    Rollback(RollbackCommand),
     Get(GetCommand),
     Rem(RemCommand),
     SetDb(SetDbCommand),
@@ -49,68 +97,497 @@ pub enum Op {
     Create(CreateCommand),
     Delete(DeleteCommand),
     Clear(ClearCommand),
-    
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Set the given key and value")]
 pub struct SetCommand {
+    /// The key
     pub key: String,
-    pub value: String,
+
+    /// The value (the path for the file types), `-` reads it from stdin and it
+    /// is prompted without echo when left out
+    #[clap(conflicts_with_all = &["from-file", "generate", "fields", "secret-fields"])]
+    pub value: Option<String>,
+
+    /// Read the value from the given file, as is
+    #[clap(long, value_name = "PATH", conflicts_with = "generate")]
+    pub from_file: Option<PathBuf>,
+
+    /// Generate a random password (or passphrase) as the value
+    #[clap(short, long)]
+    pub generate: bool,
+
+    /// Print the generated value
+    #[clap(long, requires = "generate")]
+    pub show: bool,
+
+    #[clap(flatten)]
+    pub gen: GenArgs,
+
+    #[clap(flatten)]
+    pub otp: OtpArgs,
+
+    /// Set a field of a record (--type record/<login|card|note>), `name=-`
+    /// reads the value from stdin, it is prompted without echo for `name` and
+    /// an empty value removes the field. Can be repeated
+    #[clap(long = "field", value_name = "NAME=VALUE", conflicts_with = "generate")]
+    pub fields: Vec<String>,
+
+    /// Set a sensitive field of a record (encrypted and masked), see --field
+    #[clap(
+        long = "secret-field",
+        value_name = "NAME=VALUE",
+        conflicts_with = "generate"
+    )]
+    pub secret_fields: Vec<String>,
+
+    /// Don't encrypt the value, the password isn't asked
     #[clap(short, long)]
     pub no_password: bool,
+
+    /// The type of the value: data/<str|byte>, <file|image|video>/<extension>,
+    /// otp or record/<login|card|note> (default: data/str)
     #[clap(short, long)]
     pub r#type: Option<String>,
 
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+
+    /// A note about the key, an empty one removes it
+    #[clap(long)]
+    pub note: Option<String>,
+
+    /// Add a tag to the key, can be repeated
+    #[clap(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// The value expires after the given time, e.g. 30m, 8h or 7d
+    #[clap(long, conflicts_with = "expires")]
+    pub ttl: Option<String>,
+
+    /// The value expires at the given date, e.g. 2026-10-16 or 2026-10-16 18:00
+    #[clap(long, value_name = "DATE")]
+    pub expires: Option<String>,
+}
+
+// The parameters of an otp secret (--type otp), they override the ones of an
+// otpauth:// URI
+#[derive(Debug, Args)]
+pub struct OtpArgs {
+    /// The hash function of the otp codes (default: sha1)
+    #[clap(long, value_enum)]
+    pub algorithm: Option<OtpAlgorithm>,
+
+    /// The number of digits of the otp codes (default: 6)
+    #[clap(long)]
+    pub digits: Option<u32>,
+
+    /// The number of seconds each TOTP code is valid for (default: 30)
+    #[clap(long)]
+    pub period: Option<u64>,
+
+    /// The next counter of a HOTP, the secret is a HOTP when it is given
+    #[clap(long)]
+    pub counter: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Print the current code of an otp secret and the seconds it is valid for")]
+pub struct TotpCommand {
+    /// The key
+    pub key: String,
+
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Generate a random password or passphrase")]
+pub struct GenCommand {
+    #[clap(flatten)]
+    pub gen: GenArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct GenArgs {
+    /// The number of characters of the password
+    #[clap(long, default_value_t = 24)]
+    pub length: usize,
+
+    /// The classes of characters of the password, comma separated
+    #[clap(
+        long,
+        value_enum,
+        use_value_delimiter = true,
+        default_value = "upper,lower,digits,symbols"
+    )]
+    pub charset: Vec<Charset>,
+
+    /// Leave out the characters that look alike (I, l, 1, |, O, 0, o)
+    #[clap(long)]
+    pub no_ambiguous: bool,
+
+    /// Generate a passphrase of the given number of words instead
+    #[clap(long, conflicts_with_all = &["length", "charset", "no-ambiguous"])]
+    pub words: Option<usize>,
+
+    /// The separator of the words of the passphrase
+    #[clap(long, default_value = "-")]
+    pub separator: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Charset {
+    Upper,
+    Lower,
+    Digits,
+    Symbols,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Change the note and the tags of a key, the value is left as is")]
+pub struct MetaCommand {
+    /// The key
+    pub key: String,
+
+    /// The database (default: the current database)
     #[clap(short, long)]
     pub db: Option<String>,
+
+    /// The note about the key, an empty one removes it
+    #[clap(long)]
+    pub note: Option<String>,
+
+    /// Add a tag to the key, can be repeated
+    #[clap(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// Remove a tag from the key, can be repeated
+    #[clap(long = "untag", value_name = "TAG")]
+    pub untags: Vec<String>,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Get the value of a given key")]
 pub struct GetCommand {
+    /// The key
     pub key: String,
+
+    /// Write the value (or the file) to the given path
     #[clap(short, long)]
     pub out: Option<String>,
+
+    /// Show the sensitive fields of a record
+    #[clap(long)]
+    pub reveal: bool,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "List the earlier values of a key, the most recent first")]
+pub struct HistoryCommand {
+    /// The key
+    pub key: String,
+
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Restore an earlier value of a key, the current one is kept in its history")]
+pub struct RollbackCommand {
+    /// The key
+    pub key: String,
+
+    /// The number of the earlier value (see `yor history`), 1 is the most recent
+    #[clap(long, default_value_t = 1)]
+    pub to: usize,
+
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Set the default database")]
 pub struct SetDbCommand {
-    // The name of the database
+    /// The name of the database
     pub name: String,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Remove a key from the database")]
 pub struct RemCommand {
-    // The key to be remove
+    /// The key to be remove
     pub key: String,
+
+    /// Don't ask for a confirmation
+    #[clap(short, long)]
+    pub yes: bool,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Create a new empty database")]
 pub struct CreateCommand {
-    // The name of the database
+    /// The name of the database
     pub name: String,
+
+    /// Create the database without a master password
+    #[clap(short, long, conflicts_with = "encrypted")]
+    pub no_password: bool,
+
+    /// Encrypt the whole database (key names and types included) with the master password
+    #[clap(short, long)]
+    pub encrypted: bool,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "Delete the given database name")]
 pub struct DeleteCommand {
-    // The name of the database
+    /// The name of the database
     pub name: String,
+
+    /// Don't ask for a confirmation
+    #[clap(short, long)]
+    pub yes: bool,
 }
 
 #[derive(Debug, Args)]
-#[clap(about = "Clear the given environment")]
+#[clap(about = "Clear the given environment, the databases are moved to the trash")]
 pub struct ClearCommand {
-    pub name: String,
+    /// The directory of the vault to clear
+    #[clap(value_enum)]
+    pub name: Environment,
+
+    /// Don't ask for a confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+}
+
+/// The directories of the vault that can be cleared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Environment {
+    /// The databases
+    Db,
+    /// The files extracted by get
+    Files,
+    /// The stored files of the keys
+    Blobs,
+    /// The removed databases
+    Trash,
 }
 
 #[derive(Debug, Args)]
 #[clap(about = "List all keys avaialable from the database")]
 pub struct ListKeysCommand {
+    /// The database (default: the current database)
     #[clap(short, long)]
     pub db: Option<String>,
+
+    /// Only list the keys with the given tag, can be repeated
+    #[clap(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// Sort the keys by name or time, the oldest first
+    #[clap(long, value_enum, default_value = "key")]
+    pub sort: SortKey,
+
+    /// Show the times, the tags and the note of the keys
+    #[clap(short, long)]
+    pub long: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Key,
+    Created,
+    Updated,
+    Accessed,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Create a project-local vault (.yor) used from the directory and its children")]
+pub struct InitCommand {
+    /// The directory of the project (default: the current directory)
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Load all keys from the current database into environment variable")]
+pub struct LoadEnvCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+
+    /// The shell to print the exports for (detected from $SHELL if not given)
+    #[clap(short, long, possible_values = ["bash", "zsh", "fish", "powershell"])]
+    pub shell: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Run a command with keys from the database injected into its environment")]
+pub struct ExecCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+
+    /// Only inject the given keys, a trailing `*` matches a prefix (e.g. `db.*`)
+    #[clap(short, long = "key")]
+    pub keys: Vec<String>,
+
+    /// Map a key prefix to a variable prefix, e.g. `db.=POSTGRES_`
+    #[clap(short, long = "map")]
+    pub maps: Vec<String>,
+
+    /// The command to run and its arguments
+    #[clap(required = true, last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Show or change the configuration")]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    pub command: ConfigOp,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigOp {
+    Kdf(KdfConfigCommand),
+    History(HistoryConfigCommand),
+    Trash(TrashConfigCommand),
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Show or set the number of earlier values kept for each key of a database")]
+pub struct HistoryConfigCommand {
+    /// The number of earlier values, 0 keeps none
+    pub depth: Option<usize>,
+
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Show or set the number of days the removed keys and databases are kept")]
+pub struct TrashConfigCommand {
+    /// The number of days, 0 keeps them until the trash is emptied
+    pub days: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Show or set the key derivation parameters used for new database keys")]
+pub struct KdfConfigCommand {
+    /// The memory used by Argon2i in KiB
+    #[clap(short, long)]
+    pub memory: Option<u32>,
+
+    /// The number of Argon2i iterations
+    #[clap(short, long)]
+    pub iterations: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Change the password of the encrypted keys or of the whole database")]
+pub struct RekeyCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+
+    /// Only rotate the given keys, a trailing `*` matches a prefix (e.g. `aws.*`)
+    #[clap(short, long = "key")]
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Start the agent that keeps the unlocked databases, or show its state")]
+pub struct AgentCommand {
+    /// The agent forgets the keys and exits after this idle time, e.g. 30m
+    /// or 8h (default: 15m)
+    #[clap(short, long)]
+    pub timeout: Option<String>,
+
+    /// Run the agent in the foreground
+    #[clap(long)]
+    pub foreground: bool,
+
+    /// Stop the running agent
+    #[clap(long, conflicts_with_all = &["timeout", "foreground"])]
+    pub stop: bool,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Unlock a database in the agent, its password isn't asked again")]
+pub struct UnlockCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Make the agent forget the keys of the databases")]
+pub struct LockCommand {
+    /// Only lock the given database
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "List, restore or empty the removed keys and deleted databases")]
+pub struct TrashCommand {
+    #[clap(subcommand)]
+    pub command: TrashOp,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrashOp {
+    Ls(TrashLsCommand),
+    Restore(TrashRestoreCommand),
+    Empty(TrashEmptyCommand),
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "List the deleted databases and the removed keys of the database")]
+pub struct TrashLsCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Restore a removed key (or a deleted database with --database)")]
+pub struct TrashRestoreCommand {
+    /// The key, or the database with --database
+    pub name: String,
+
+    /// Restore the deleted database with the given name
+    #[clap(long, conflicts_with = "db")]
+    pub database: bool,
+
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Remove the deleted databases and the removed keys of the database for good")]
+pub struct TrashEmptyCommand {
+    /// The database (default: the current database)
+    #[clap(short, long)]
+    pub db: Option<String>,
+
+    /// Don't ask for a confirmation
+    #[clap(short, long)]
+    pub yes: bool,
 }
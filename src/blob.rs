@@ -0,0 +1,440 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Blob store for the files, images and videos.
+//!
+//! Files are stored outside the database under `<vault root>/blobs/<id>`, the id
+//! being the BLAKE2b-256 hash of the stored file. Protected files are
+//! encrypted with a chunked streaming AEAD (XChaCha20-Poly1305 secretstream)
+//! so the memory use doesn't depend on the size of the file.
+//!
+//! ## Format
+//!
+//! {0,29: cipher header (see `yor::encrypt_with`)} {29,45: salt of the
+//! subkey (see `yor::DbKey`)} {45,61: check value of the database key}
+//! {61,85: nonce}
+//! {85,: chunks of CHUNK_SIZE + ABYTES bytes, the last one is tagged `Finish`}
+//!
+//! Each chunk is authenticated with the header and the `AssociatedData` of
+//! its key, so a blob referenced from another key or database doesn't
+//! decrypt. The blobs written before (version 1 of the header) have no check
+//! value nor associated data, and the ones written before the database keys
+//! have no subkey salt either, their key is derived from the password.
+
+use crate::error::YorError;
+use crate::yor::{
+    get_key_from_password, nonce, parse_cipher_header, salt, sync_dir, vault_root, AssociatedData,
+    DbKey, ALG_ARGON2I_XCHACHA20POLY1305_STREAM, ALG_DBKEY_XCHACHA20POLY1305_STREAM,
+    CIPHER_HEADER_SIZE, CIPHER_VERSION_DB_ID, KEY_CHECK_SIZE, SALT_SIZE,
+};
+use anyhow::{bail, Context, Result};
+use orion::hazardous::aead::streaming::{
+    Nonce, SecretKey, StreamTag, StreamXChaCha20Poly1305, ABYTES,
+};
+use orion::hazardous::hash::blake2::blake2b::Blake2b;
+use orion::hazardous::stream::xchacha20::XCHACHA_NONCESIZE;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Size of the plaintext of each encrypted chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+const HASH_SIZE: usize = 32;
+
+/// A file stored in the blob store, referenced from the database
+#[derive(Serialize, Deserialize, Clone)]
+pub struct YorBlob {
+    /// The hex encoded BLAKE2b-256 hash of the stored file
+    pub id: String,
+    /// The size of the original file
+    pub size: u64,
+    /// If the stored file is encrypted
+    pub encrypted: bool,
+}
+
+/// Get the directory of the blob store
+pub fn get_blob_dir() -> PathBuf {
+    vault_root().join("blobs")
+}
+
+/// Get the path of the given blob
+pub fn get_blob_path(id: &str) -> PathBuf {
+    get_blob_dir().join(id)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Fill the buffer from the reader, returns less than the buffer size only at
+/// the end of the reader.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Writer to a temporary file of the blob store that hashes everything written
+struct BlobWriter {
+    file: fs::File,
+    tmp: PathBuf,
+    hasher: Blake2b,
+}
+
+impl BlobWriter {
+    fn new() -> Result<Self> {
+        let dir = get_blob_dir();
+        fs::create_dir_all(&dir)?;
+        let tmp = dir.join(format!(".tmp-{}", to_hex(&salt()?)));
+        Ok(BlobWriter {
+            file: fs::File::create(&tmp).with_context(|| "Cannot write the blob")?,
+            tmp,
+            hasher: Blake2b::new(HASH_SIZE)?,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.hasher.update(data)?;
+        self.file
+            .write_all(data)
+            .with_context(|| "Cannot write the blob")
+    }
+
+    /// Move the temporary file to its content address and return the id
+    fn finish(mut self) -> Result<String> {
+        self.file.sync_all()?;
+        let id = to_hex(self.hasher.finalize()?.as_ref());
+        let path = get_blob_path(&id);
+        fs::rename(&self.tmp, &path).with_context(|| "Cannot write the blob")?;
+        sync_dir(&path)?;
+        Ok(id)
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        // Only left behind if `finish` wasn't reached
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+/// Store the content of the reader in the blob store, encrypted with a
+/// subkey of the database key unless there's none (or its password is empty).
+///
+/// # Arguments
+/// - `reader` - The content to store
+/// - `key` - The key to encrypt the content with, if any
+/// - `ad` - What the blob belongs to, authenticated with the encrypted content
+pub fn store_blob(
+    mut reader: impl Read,
+    key: Option<&DbKey>,
+    ad: &AssociatedData,
+) -> Result<YorBlob> {
+    let mut writer = BlobWriter::new()?;
+    let mut size = 0u64;
+
+    let key = match key.filter(|key| !key.is_empty()) {
+        Some(key) => key,
+        None => {
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let n = read_full(&mut reader, &mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                writer.write(&buf[..n])?;
+                size += n as u64;
+            }
+            return Ok(YorBlob {
+                id: writer.finish()?,
+                size,
+                encrypted: false,
+            });
+        }
+    };
+
+    let nonce = nonce()?;
+    let (mut header, subkey) =
+        key.new_subkey(CIPHER_VERSION_DB_ID, ALG_DBKEY_XCHACHA20POLY1305_STREAM)?;
+    let subkey = SecretKey::from_slice(subkey.unprotected_as_bytes())?;
+    let mut stream = StreamXChaCha20Poly1305::new(&subkey, &Nonce::from_slice(&nonce)?);
+    let ad = ad.to_bytes(&header);
+    header.extend_from_slice(&nonce);
+    writer.write(&header)?;
+
+    // Read one chunk ahead to know which one is the last
+    let mut current = vec![0u8; CHUNK_SIZE];
+    let mut next = vec![0u8; CHUNK_SIZE];
+    let mut sealed = vec![0u8; CHUNK_SIZE + ABYTES];
+    let mut current_len = read_full(&mut reader, &mut current)?;
+    loop {
+        let next_len = read_full(&mut reader, &mut next)?;
+        let tag = if next_len == 0 {
+            StreamTag::Finish
+        } else {
+            StreamTag::Message
+        };
+        stream.seal_chunk(
+            &current[..current_len],
+            Some(&ad),
+            &mut sealed[..current_len + ABYTES],
+            &tag,
+        )?;
+        writer.write(&sealed[..current_len + ABYTES])?;
+        size += current_len as u64;
+
+        if next_len == 0 {
+            break;
+        }
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+
+    Ok(YorBlob {
+        id: writer.finish()?,
+        size,
+        encrypted: true,
+    })
+}
+
+/// Reader of a stored blob file that checks its content address at the end
+struct HashingReader {
+    file: fs::File,
+    hasher: Blake2b,
+    id: String,
+    verified: bool,
+}
+
+impl Read for HashingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.verified {
+            return Ok(0);
+        }
+        let n = self.file.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let digest = self
+                .hasher
+                .finalize()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid blob hash"))?;
+            if to_hex(digest.as_ref()) != self.id {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The blob is corrupted",
+                ));
+            }
+            self.verified = true;
+            return Ok(0);
+        }
+        self.hasher
+            .update(&buf[..n])
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid blob hash"))?;
+        Ok(n)
+    }
+}
+
+/// Reader that decrypts the chunks of an encrypted blob
+struct DecryptingReader {
+    inner: HashingReader,
+    stream: StreamXChaCha20Poly1305,
+    /// The associated data of the chunks, `None` for the older blobs
+    ad: Option<Vec<u8>>,
+    sealed: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+    len: usize,
+    finished: bool,
+}
+
+impl DecryptingReader {
+    /// Decrypt the next chunk into the plaintext buffer
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let n = read_full(&mut self.inner, &mut self.sealed)?;
+        if n < ABYTES {
+            return Err(invalid("The blob is truncated"));
+        }
+        // The key of the blobs with associated data is checked before
+        let tag = self
+            .stream
+            .open_chunk(&self.sealed[..n], self.ad.as_deref(), &mut self.plain)
+            .map_err(|_| {
+                let error = match self.ad {
+                    Some(_) => YorError::Corrupt(String::from(
+                        "The file was tampered with or moved from another key",
+                    )),
+                    None => YorError::WrongPassword,
+                };
+                io::Error::new(io::ErrorKind::InvalidData, error)
+            })?;
+        self.pos = 0;
+        self.len = n - ABYTES;
+
+        if let StreamTag::Finish = tag {
+            self.finished = true;
+            // Nothing may follow the last chunk, this also checks the hash
+            if read_full(&mut self.inner, &mut [0u8; 1])? != 0 {
+                return Err(invalid("The blob is corrupted"));
+            }
+        } else if n < self.sealed.len() {
+            return Err(invalid("The blob is truncated"));
+        }
+        Ok(())
+    }
+}
+
+impl Read for DecryptingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.len {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Open the given blob for reading its original content. The key is
+/// checked right away for encrypted blobs.
+///
+/// # Arguments
+/// - `blob` - The blob to open
+/// - `key` - The key the blob is encrypted with, needed if it is encrypted
+/// - `ad` - What the blob belongs to, see `store_blob`
+pub fn open_blob(
+    blob: &YorBlob,
+    key: Option<&DbKey>,
+    ad: &AssociatedData,
+) -> Result<Box<dyn Read>> {
+    let file = fs::File::open(get_blob_path(&blob.id))
+        .with_context(|| format!("Blob: {} not found", blob.id))?;
+    let mut inner = HashingReader {
+        file,
+        hasher: Blake2b::new(HASH_SIZE)?,
+        id: blob.id.clone(),
+        verified: false,
+    };
+    if !blob.encrypted {
+        return Ok(Box::new(inner));
+    }
+
+    let key = key.ok_or(YorError::WrongPassword)?;
+
+    // The size of the rest of the header depends on the algorithm
+    let mut header = vec![0u8; CIPHER_HEADER_SIZE];
+    if read_full(&mut inner, &mut header)? != header.len() {
+        bail!(YorError::Corrupt(String::from("The blob is truncated")));
+    }
+    let rest = match header[4] {
+        ALG_ARGON2I_XCHACHA20POLY1305_STREAM => XCHACHA_NONCESIZE,
+        ALG_DBKEY_XCHACHA20POLY1305_STREAM if header[3] >= CIPHER_VERSION_DB_ID => {
+            SALT_SIZE + KEY_CHECK_SIZE + XCHACHA_NONCESIZE
+        }
+        ALG_DBKEY_XCHACHA20POLY1305_STREAM => SALT_SIZE + XCHACHA_NONCESIZE,
+        algorithm => bail!(YorError::Corrupt(format!(
+            "Unsupported encryption algorithm: {}",
+            algorithm
+        ))),
+    };
+    header.resize(CIPHER_HEADER_SIZE + rest, 0);
+    if read_full(&mut inner, &mut header[CIPHER_HEADER_SIZE..])? != rest {
+        bail!(YorError::Corrupt(String::from("The blob is truncated")));
+    }
+    let parsed = match parse_cipher_header(&header) {
+        Ok(Some(parsed)) => parsed,
+        _ => bail!(YorError::Corrupt(String::from("The blob is corrupted"))),
+    };
+    let (subkey, nonce) = match parsed.algorithm {
+        ALG_DBKEY_XCHACHA20POLY1305_STREAM => key.open_subkey(&parsed)?,
+        _ => (
+            get_key_from_password(
+                key.password().ok_or(YorError::WrongPassword)?,
+                parsed.salt,
+                &parsed.params,
+            )?,
+            parsed.body,
+        ),
+    };
+    let subkey = SecretKey::from_slice(subkey.unprotected_as_bytes())?;
+    let stream = StreamXChaCha20Poly1305::new(&subkey, &Nonce::from_slice(nonce)?);
+    let ad = (parsed.version >= CIPHER_VERSION_DB_ID)
+        .then(|| ad.to_bytes(&header[..header.len() - XCHACHA_NONCESIZE]));
+
+    let mut reader = DecryptingReader {
+        inner,
+        stream,
+        ad,
+        sealed: vec![0u8; CHUNK_SIZE + ABYTES],
+        plain: vec![0u8; CHUNK_SIZE],
+        pos: 0,
+        len: 0,
+        finished: false,
+    };
+    // Fail early on a wrong password
+    reader.next_chunk().map_err(YorError::from)?;
+    Ok(Box::new(reader))
+}
+
+/// Write the original content of the blob to the given path. Nothing is left
+/// at the path if the blob can't be read.
+///
+/// # Arguments
+/// - `blob` - The blob to restore
+/// - `key` - The key the blob is encrypted with, needed if it is encrypted
+/// - `ad` - What the blob belongs to, see `store_blob`
+/// - `path` - Where to write the file
+pub fn restore_blob(
+    blob: &YorBlob,
+    key: Option<&DbKey>,
+    ad: &AssociatedData,
+    path: &Path,
+) -> Result<()> {
+    let mut reader = open_blob(blob, key, ad)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let result = fs::File::create(&tmp)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| {
+            io::copy(&mut reader, &mut file)?;
+            Ok(file.sync_all()?)
+        })
+        .and_then(|_| Ok(fs::rename(&tmp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| "Cannot write the file")
+}
+
+/// Remove the blob file from the store
+pub fn remove_blob(blob: &YorBlob) -> Result<()> {
+    fs::remove_file(get_blob_path(&blob.id)).with_context(|| "Cannot remove the blob")
+}
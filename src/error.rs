@@ -0,0 +1,132 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+use std::fmt;
+use std::io;
+
+/// Errors returned by yor, each kind has its own exit code so wrapper
+/// scripts can branch on them.
+///
+/// | code | kind             |
+/// |------|------------------|
+/// | 1    | `invalid`/`other`|
+/// | 2    | usage (clap)     |
+/// | 3    | `not_found`      |
+/// | 4    | `wrong_password` |
+/// | 5    | `corrupt`        |
+/// | 6    | `io`             |
+#[derive(Debug, Clone)]
+pub enum YorError {
+    /// A database, key or file doesn't exist
+    NotFound(String),
+    /// The password can't decrypt the data
+    WrongPassword,
+    /// A database or an encrypted value can't be read
+    Corrupt(String),
+    /// Reading or writing a file failed
+    Io(String),
+    /// The input is invalid (type, key name, confirmation...)
+    Invalid(String),
+    Other(String),
+}
+
+impl YorError {
+    /// The exit code of the process for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            YorError::Invalid(_) | YorError::Other(_) => 1,
+            YorError::NotFound(_) => 3,
+            YorError::WrongPassword => 4,
+            YorError::Corrupt(_) => 5,
+            YorError::Io(_) => 6,
+        }
+    }
+
+    /// A stable name of the error kind, for the json output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            YorError::NotFound(_) => "not_found",
+            YorError::WrongPassword => "wrong_password",
+            YorError::Corrupt(_) => "corrupt",
+            YorError::Io(_) => "io",
+            YorError::Invalid(_) => "invalid",
+            YorError::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for YorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            YorError::WrongPassword => write!(f, "Password is invalid."),
+            YorError::NotFound(m)
+            | YorError::Corrupt(m)
+            | YorError::Io(m)
+            | YorError::Invalid(m)
+            | YorError::Other(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for YorError {}
+
+impl From<io::Error> for YorError {
+    fn from(e: io::Error) -> Self {
+        // Readers report the errors of the decryption as io errors
+        if let Some(err) = e.get_ref().and_then(|i| i.downcast_ref::<YorError>()) {
+            return err.clone();
+        }
+        match e.kind() {
+            io::ErrorKind::NotFound => YorError::NotFound(e.to_string()),
+            io::ErrorKind::InvalidData => YorError::Corrupt(e.to_string()),
+            _ => YorError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<pickledb::error::Error> for YorError {
+    fn from(e: pickledb::error::Error) -> Self {
+        match e.get_type() {
+            pickledb::error::ErrorType::Io => YorError::Io(e.to_string()),
+            pickledb::error::ErrorType::Serialization => YorError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for YorError {
+    fn from(e: serde_json::Error) -> Self {
+        YorError::Corrupt(e.to_string())
+    }
+}
+
+impl From<anyhow::Error> for YorError {
+    /// Keep the kind of a `YorError` wrapped in context, anything else is `Other`
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(err) = e.chain().find_map(|c| c.downcast_ref::<YorError>()) {
+            return err.clone();
+        }
+        if let Some(err) = e.chain().find_map(|c| c.downcast_ref::<io::Error>()) {
+            if let Some(err) = err.get_ref().and_then(|i| i.downcast_ref::<YorError>()) {
+                return err.clone();
+            }
+            return YorError::from(io::Error::new(err.kind(), format!("{:#}", e)));
+        }
+        YorError::Other(format!("{:#}", e))
+    }
+}
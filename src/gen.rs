@@ -0,0 +1,178 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Password and passphrase generator.
+//!
+//! Everything is drawn from the random generator of the operating system
+//! (`OsRng`). Passphrases are picked from the EFF large wordlist (7776 words,
+//! about 12.9 bits each), see <https://www.eff.org/dice> (CC BY 3.0 US).
+
+use crate::error::YorError;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGITS: &str = "0123456789";
+/// Quotes, backslash and backtick are left out so the passwords can be pasted
+/// in a shell or a config file
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+/// Characters that are easily mistaken for one another
+const AMBIGUOUS: &str = "Il1|O0o";
+
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+/// The classes of characters of a generated password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Upper,
+    Lower,
+    Digits,
+    Symbols,
+}
+
+impl CharClass {
+    fn chars(self) -> &'static str {
+        match self {
+            CharClass::Upper => UPPER,
+            CharClass::Lower => LOWER,
+            CharClass::Digits => DIGITS,
+            CharClass::Symbols => SYMBOLS,
+        }
+    }
+}
+
+/// Generate a random password with at least one character of each class.
+///
+/// # Arguments
+/// - `length` - The number of characters
+/// - `classes` - The classes of characters to use
+/// - `no_ambiguous` - Leave out the characters like `l`, `1`, `O` and `0`
+pub fn password(
+    length: usize,
+    classes: &[CharClass],
+    no_ambiguous: bool,
+) -> Result<String, YorError> {
+    let sets: Vec<Vec<char>> = classes
+        .iter()
+        .map(|class| {
+            class
+                .chars()
+                .chars()
+                .filter(|c| !no_ambiguous || !AMBIGUOUS.contains(*c))
+                .collect()
+        })
+        .collect();
+    if sets.is_empty() {
+        return Err(YorError::Invalid(String::from(
+            "At least one class of characters is needed.",
+        )));
+    }
+    if length < sets.len() {
+        return Err(YorError::Invalid(format!(
+            "The password must be at least {} characters long, one of each class.",
+            sets.len()
+        )));
+    }
+
+    let all: Vec<char> = sets.concat();
+    let mut rng = OsRng;
+    let mut chars: Vec<char> = sets
+        .iter()
+        .filter_map(|set| set.choose(&mut rng).copied())
+        .collect();
+    while chars.len() < length {
+        chars.extend(all.choose(&mut rng));
+    }
+    chars.shuffle(&mut rng);
+    Ok(chars.into_iter().collect())
+}
+
+/// Generate a passphrase of random words from the wordlist.
+///
+/// # Arguments
+/// - `words` - The number of words
+/// - `separator` - The separator between the words
+pub fn passphrase(words: usize, separator: &str) -> Result<String, YorError> {
+    if words == 0 {
+        return Err(YorError::Invalid(String::from(
+            "A passphrase needs at least one word.",
+        )));
+    }
+    let wordlist: Vec<&str> = WORDLIST.lines().collect();
+    let mut rng = OsRng;
+    let picked: Vec<&str> = (0..words)
+        .filter_map(|_| wordlist.choose(&mut rng).copied())
+        .collect();
+    Ok(picked.join(separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [CharClass; 4] = [
+        CharClass::Upper,
+        CharClass::Lower,
+        CharClass::Digits,
+        CharClass::Symbols,
+    ];
+
+    #[test]
+    fn password_has_each_class() {
+        for length in [4, 5, 24, 64] {
+            let generated = password(length, &ALL, false).unwrap();
+            assert_eq!(generated.chars().count(), length);
+            for class in ALL {
+                assert!(generated.chars().any(|c| class.chars().contains(c)));
+            }
+        }
+        let digits = password(12, &[CharClass::Digits], false).unwrap();
+        assert!(digits.chars().all(|c| c.is_ascii_digit()));
+
+        // Shorter than the number of classes
+        assert!(password(3, &ALL, false).is_err());
+        assert!(password(1, &[CharClass::Upper, CharClass::Lower], false).is_err());
+        assert!(password(0, &[CharClass::Digits], false).is_err());
+        assert!(password(24, &[], false).is_err());
+        assert_eq!(password(1, &[CharClass::Upper], false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn password_without_ambiguous_chars() {
+        for _ in 0..50 {
+            let generated = password(64, &ALL, true).unwrap();
+            assert!(!generated.chars().any(|c| AMBIGUOUS.contains(c)));
+            for class in ALL {
+                assert!(generated.chars().any(|c| class.chars().contains(c)));
+            }
+        }
+    }
+
+    #[test]
+    fn passphrase_words() {
+        let wordlist: Vec<&str> = WORDLIST.lines().collect();
+        let generated = passphrase(6, " ").unwrap();
+        let words: Vec<&str> = generated.split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|word| wordlist.contains(word)));
+        assert!(wordlist.contains(&passphrase(1, "-").unwrap().as_str()));
+        assert!(passphrase(0, "-").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Yet another secure personal key-value storage vault.
+//!
+//! The `yor` binary is a front-end over this crate, the databases it
+//! writes can be read directly with a `Vault`:
+//!
+//! ```no_run
+//! use yor::Vault;
+//!
+//! # fn main() -> Result<(), yor::YorError> {
+//! let mut vault = Vault::open("/home/zenn/.local/share/yor/db/work", Some("password"))?;
+//! vault.set("token", "s3cr3t")?;
+//! let token = vault.get_string("token")?;
+//! for (key, data) in vault.iter() {
+//!     println!("{} ({})", key, data.y_type);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod agent;
+pub mod blob;
+pub mod error;
+pub mod gen;
+pub mod otp;
+pub mod vault;
+mod yor;
+
+pub use crate::error::YorError;
+pub use crate::vault::{EntryDetails, Vault};
+pub use crate::yor::*;
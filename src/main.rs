@@ -19,82 +19,274 @@
 
 use clap::Parser;
 use colored::Colorize;
-use dialoguer::Confirm;
 use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use yor::{Vault, YorError};
 mod args;
-mod yor;
+mod output;
 
 fn main() {
-    yor::initialize_env().unwrap();
     let a: args::YorParser = args::YorParser::parse();
+    let format = output::resolve_format(a.format, a.json);
+    if let Err(e) = run(a, format) {
+        if format == args::Format::Json {
+            let error = serde_json::json!({
+                "error": {
+                    "kind": e.kind(),
+                    "message": e.to_string(),
+                    "code": e.exit_code(),
+                }
+            });
+            eprintln!("{}", error);
+        } else {
+            eprintln!("{}", e.to_string().truecolor(157, 123, 125));
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(a: args::YorParser, format: args::Format) -> Result<(), YorError> {
+    yor::set_vault_root(a.home)?;
+    yor::initialize_env()?;
+    let password_stdin = a.password_stdin;
+    yor::set_password_source(password_stdin, a.password_file);
     match a.command {
         args::Op::Get(v) => {
-            let conf = yor::get_config_data();
-            let db_name = conf.get::<String>("db_name").unwrap();
-            let data = yor::get_item(db_name, v.key, v.out);
-            println!("{}", data.truecolor(138, 172, 171));
+            let db_name = yor::get_db_name()?;
+            if v.out.is_none() {
+                if let Some(fields) = yor::get_record(&db_name, &v.key, v.reveal)? {
+                    output::print_record(format, &db_name, &v.key, fields);
+                    return Ok(());
+                }
+            }
+            let data = yor::get_item(&db_name, &v.key, v.out)?;
+            output::print_value(format, &db_name, &v.key, data)?;
         }
         args::Op::Set(v) => {
-            let db = yor::get_config_data();
-            let mut db_name = db.get::<String>("db_name").unwrap();
-            let mut pwd = db.get::<String>("db_key").unwrap_or(String::from(""));
+            let mut db_name = yor::get_db_name()?;
+            // The kind of a record (record/<kind>), it is only needed to create one
+            let record_kind = v
+                .r#type
+                .as_deref()
+                .and_then(|t| t.strip_prefix("record/"))
+                .map(String::from);
+            let is_record =
+                record_kind.is_some() || !v.fields.is_empty() || !v.secret_fields.is_empty();
+            if is_record && record_kind.is_none() && v.r#type.is_some() {
+                return Err(YorError::Invalid(String::from(
+                    "The fields need a record type: record/<login|card|note>.",
+                )));
+            }
             let r#type = v.r#type.unwrap_or("data/str".to_string());
-            if pwd == "" && !v.no_password {
-                let _pwd = yor::get_password("[yor] password to be set: ");
-                if _pwd != "" {
-                    let _confirm_pwd = yor::get_password("[yor] confirm password: ");
-                    if _pwd == _confirm_pwd {
-                        pwd = _pwd
-                    } else {
-                        println!("{}", "Password does not match.".truecolor(157, 123, 125));
-                        std::process::exit(1);
-                    }
-                } else {
-
-                    pwd = _pwd
+            if let Some(db) = v.db {
+                db_name = db;
+            }
 
+            let expires_at = match (&v.ttl, &v.expires) {
+                (Some(ttl), _) => {
+                    let seconds = yor::parse_ttl(ttl)?;
+                    let expires_at = yor::now().checked_add(seconds).ok_or_else(|| {
+                        YorError::Invalid(format!("The time-to-live is too long: {}", ttl))
+                    })?;
+                    Some(expires_at)
                 }
+                (None, Some(date)) => Some(yor::parse_date(date)?),
+                (None, None) => None,
+            };
+
+            // `otp` is either `otp/totp` or `otp/hotp`, depending on the secret
+            let is_otp = r#type.split('/').next() == Some("otp");
+            let otp_args = &v.otp;
+            if !is_otp
+                && (otp_args.algorithm.is_some()
+                    || otp_args.digits.is_some()
+                    || otp_args.period.is_some()
+                    || otp_args.counter.is_some())
+            {
+                return Err(YorError::Invalid(String::from(
+                    "The otp parameters need --type otp.",
+                )));
             }
-            if !v.db.is_none() {
-                db_name = v.db.unwrap();
+
+            // The value is read before the passwords are prompted
+            let is_file = yor::FILE_TYPES.contains(&r#type.split('/').next().unwrap_or_default());
+            let fields = read_fields(&v.fields, &v.secret_fields, password_stdin)?;
+            let value = if is_record {
+                None
+            } else if v.generate {
+                if is_file {
+                    return Err(YorError::Invalid(String::from(
+                        "Only the data types can be generated.",
+                    )));
+                }
+                Some(generate(&v.gen)?.into_bytes())
+            } else if is_file {
+                None
+            } else {
+                Some(read_value(
+                    v.value.clone(),
+                    v.from_file.clone(),
+                    password_stdin,
+                )?)
+            };
+
+            let mut vault = Vault::load(&db_name)?;
+            let fields: Vec<(String, Vec<u8>, bool)> = match &record_kind {
+                Some(kind) => fields
+                    .into_iter()
+                    .map(|(name, value, secret)| {
+                        let sensitive = secret || yor::is_sensitive_field(kind, &name);
+                        (name, value, sensitive)
+                    })
+                    .collect(),
+                // The kind of an existing record
+                None => {
+                    let y_type = vault.entry(&v.key).map(|yor| yor.y_type).ok();
+                    let kind = y_type.as_deref().and_then(|t| t.strip_prefix("record/"));
+                    fields
+                        .into_iter()
+                        .map(|(name, value, secret)| {
+                            let sensitive =
+                                secret || kind.is_some_and(|k| yor::is_sensitive_field(k, &name));
+                            (name, value, sensitive)
+                        })
+                        .collect()
+                }
+            };
+
+            if v.no_password || (is_record && !fields.iter().any(|(_, _, s)| *s)) {
+                vault.lock();
+            } else if is_record && vault.entry(&v.key).is_ok_and(|yor| yor.is_encrypted()) {
+                // The new fields use the password of the record
+                vault.prompt_unlock_key(&v.key)?;
+            } else if !vault.prompt_unlock()? {
+                // Databases with a master password encrypt every value with it
+                let pwd = yor::get_new_password("[yor] password to be set: ")?;
+                if !pwd.is_empty() {
+                    vault.unlock(&pwd)?;
+                }
             }
 
-            yor::upsert_item(db_name, pwd, v.key, v.value, r#type);
+            // Stored with the value in a single save
+            let details = yor::EntryDetails {
+                note: v.note,
+                tags: v.tags,
+                expires_at,
+            };
+            match &value {
+                Some(value) if is_otp => {
+                    let otp = parse_otp(value, &v.otp)?;
+                    vault.set_otp(&v.key, &otp, &details)?;
+                }
+                Some(value) => vault.set_value(&v.key, value, &r#type, &details)?,
+                None if is_record => {
+                    vault.set_fields(&v.key, record_kind.as_deref(), &fields, &details)?
+                }
+                None => {
+                    let path = v
+                        .from_file
+                        .or_else(|| v.value.map(PathBuf::from))
+                        .ok_or_else(|| {
+                            YorError::Invalid(String::from(
+                                "The path of the file to store is missing.",
+                            ))
+                        })?;
+                    vault.set_file(&v.key, &path, &r#type, &details)?;
+                }
+            }
+            // The generated value is only printed on request
+            if let Some(value) = value.filter(|_| v.show) {
+                output::print_value(format, &db_name, &v.key, value)?;
+            }
+        }
+        args::Op::Totp(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let mut vault = Vault::load(&db_name)?;
+            vault.prompt_unlock_key(&v.key)?;
+            let (code, remaining) = vault.otp_code(&v.key)?;
+            // The access time is best effort, like for get
+            let _ = vault.touch(&[&v.key]);
+            output::print_otp(format, &db_name, &v.key, &code, remaining);
+        }
+        args::Op::Gen(v) => {
+            println!("{}", generate(&v.gen)?);
+        }
+        args::Op::Meta(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let mut vault = Vault::load(&db_name)?;
+            vault.annotate(&v.key, v.note.as_deref(), &v.tags, &v.untags)?;
+        }
+        args::Op::History(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let vault = Vault::load(&db_name)?;
+            output::print_history(format, &db_name, &v.key, vault.history(&v.key)?);
+        }
+        args::Op::Rollback(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let mut vault = Vault::load(&db_name)?;
+            vault.prompt_unlock_earlier(&v.key, v.to)?;
+            vault.rollback(&v.key, v.to)?;
+            println!(
+                "Key: {} is rolled back to its value #{}.",
+                v.key.truecolor(172, 169, 138),
+                v.to
+            );
         }
         args::Op::SetDb(v) => {
-            let mut db = yor::get_config_data();
+            if let yor::VaultSource::Project(yor::Project {
+                db: Some(db), path, ..
+            }) = yor::vault_source()
+            {
+                return Err(YorError::Invalid(format!(
+                    "The database is set to: {} by {}",
+                    db,
+                    path.display()
+                )));
+            }
+            let mut db = yor::get_config_data()?;
             let path = yor::get_db_path(v.name.as_str());
 
             if !path.exists() {
-                println!(
+                return Err(YorError::NotFound(format!(
                     "Database: {} not found, perhaps it doesn't exist at all?",
-                    v.name.truecolor(172, 138, 140)
-                );
-                std::process::exit(1);
+                    v.name
+                )));
             }
-            db.set("db_name", &v.name)
-                .expect("Cannot set the database name");
+            db.set("db_name", &v.name)?;
+            db.save()?;
             println!(
-                "Successfully set the database to: {}",
-                v.name.truecolor(172, 169, 138)
+                "Successfully set the database to: {} ({})",
+                v.name.truecolor(172, 169, 138),
+                describe_vault().truecolor(138, 152, 172)
             );
         }
         args::Op::Rem(v) => {
-            let db = yor::get_config_data();
-            let db_name = db.get::<String>("db_name").unwrap();
-            if Confirm::new()
-                .with_prompt(format!(
-                    "Are you sure you want to remove: {}? (action can't be undone)",
+            let db_name = yor::get_db_name()?;
+            if yor::confirm(
+                format!(
+                    "Are you sure you want to remove: {}? (it is moved to the trash)",
                     v.key
-                ))
-                .interact()
-                .unwrap()
-            {
-                yor::rem_item(&db_name, &v.key).unwrap();
+                ),
+                v.yes,
+            )? {
+                Vault::load(&db_name)?.remove(&v.key)?;
                 println!(
-                    "Key: {} from Database: {} is successfully removed.", 
+                    "Key: {} from Database: {} is moved to the trash.",
                     v.key.truecolor(172, 138, 140),
-                    db_name.truecolor(172,138,140)
+                    db_name.truecolor(172, 138, 140)
                 );
             } else {
                 println!(
@@ -107,23 +299,27 @@ fn main() {
             let path = yor::get_db_path(v.name.as_str());
 
             if !path.exists() {
-                println!(
+                return Err(YorError::NotFound(format!(
                     "Database {} doesn't exist at all",
-                    v.name.truecolor(172, 138, 140)
-                );
-                std::process::exit(1);
+                    v.name
+                )));
             }
 
-            if Confirm::new()
-                .with_prompt(format!(
-                    "Are you sure you want to delete: {}? (action can't be undone)",
+            if yor::confirm(
+                format!(
+                    "Are you sure you want to delete: {}? (it is moved to the trash)",
                     v.name
-                ))
-                .interact()
-                .unwrap()
-            {
-                fs::remove_file(path).expect("Failed to remove the file");
-                println!("Database: {} is removed.", v.name.truecolor(172, 138, 140));
+                ),
+                v.yes,
+            )? {
+                if yor::agent::is_running() {
+                    yor::agent::lock(Some(&path))?;
+                }
+                yor::trash_db(&v.name)?;
+                println!(
+                    "Database: {} is moved to the trash.",
+                    v.name.truecolor(172, 138, 140)
+                );
             } else {
                 println!(
                     "{}",
@@ -134,59 +330,531 @@ fn main() {
         args::Op::Create(v) => {
             let path = yor::get_db_path(v.name.as_str());
             if path.exists() {
-                println!(
+                return Err(YorError::Invalid(format!(
                     "It looks like database: {} is already created.",
-                    v.name.truecolor(172, 138, 140)
-                );
-                std::process::exit(1);
+                    v.name
+                )));
             }
-            yor::create_db(path.to_str().unwrap());
+            let mut pwd = String::from("");
+            if !v.no_password {
+                pwd = yor::get_new_password("[yor] master password of the database: ")?;
+            }
+            Vault::create(&path, Some(&pwd), v.encrypted)?;
+            println!("Database: {} is created.", v.name.truecolor(172, 169, 138));
         }
         args::Op::Clear(v) => {
-            let env = dirs::home_dir().unwrap().as_path().join(".yor");
-
-            let dir = env.join(&v.name);
+            // Only the directories of the vault, never a path given by the user
+            let name = match v.name {
+                args::Environment::Db => "db",
+                args::Environment::Files => "files",
+                // Referenced by the keys, they are removed with them
+                args::Environment::Blobs => {
+                    return Err(YorError::Invalid(String::from(
+                        "The stored files are removed with their keys, use rem or delete then trash empty.",
+                    )))
+                }
+                args::Environment::Trash => "trash",
+            };
+            let dir = yor::vault_root().join(name);
             if !dir.exists() {
-                println!("Cannot clear environment: `{}`. Not found", v.name);
-                std::process::exit(1);
+                return Err(YorError::NotFound(format!(
+                    "Cannot clear environment: `{}`. Not found",
+                    name
+                )));
+            }
+
+            let prompt = match v.name {
+                args::Environment::Db => "the databases are moved to the trash",
+                _ => "every file in it is deleted",
+            };
+            if yor::confirm(
+                format!("Are you sure you want to clear: {}? ({})", name, prompt),
+                v.yes,
+            )? {
+                if v.name == args::Environment::Db {
+                    // Like delete, every database can be restored
+                    for db in yor::get_all_db()? {
+                        if yor::agent::is_running() {
+                            yor::agent::lock(Some(&yor::get_db_path(&db.name)))?;
+                        }
+                        yor::trash_db(&db.name)?;
+                        println!(
+                            "Database: {} is moved to the trash.",
+                            db.name.truecolor(172, 138, 140)
+                        );
+                    }
+                } else {
+                    // Delete & Create the directory instead of deleting all the files
+                    fs::remove_dir_all(dir.clone())?;
+                    fs::create_dir_all(dir)?;
+                    println!("Environment: {} is cleared.", name.truecolor(172, 138, 140));
+                }
+                // The files of the purged databases
+                if v.name == args::Environment::Trash {
+                    sweep_blobs();
+                }
+            } else {
+                println!("{}", "Ignoring the clear request.".truecolor(172, 138, 140));
             }
-            // Delete & Create the directory instead of deleting all the files
-            fs::remove_dir_all(dir.clone()).unwrap();
-            fs::create_dir_all(dir).unwrap();
         }
         args::Op::Ls(v) => {
-            let conf = yor::get_config_data();
-            let mut db_name = conf.get::<String>("db_name").unwrap();
-            if !v.db.is_none() {
-                db_name = v.db.unwrap();
+            let mut db_name = yor::get_db_name()?;
+            if let Some(db) = v.db {
+                db_name = db;
             }
 
-            let db = yor::load_db(&yor::get_db_path(db_name.as_str())).unwrap_or_else(|_| {
+            let vault = Vault::load(&db_name)?;
+            let mut entries: Vec<_> = vault
+                .iter()
+                .filter(|(_, data)| data.has_tags(&v.tags))
+                .collect();
+            // Unknown times come first, the keys are already sorted by name
+            match v.sort {
+                args::SortKey::Key => {}
+                args::SortKey::Created => entries.sort_by_key(|(_, data)| data.created_at),
+                args::SortKey::Updated => entries.sort_by_key(|(_, data)| data.updated_at),
+                args::SortKey::Accessed => entries.sort_by_key(|(_, data)| data.last_accessed),
+            }
+            output::print_keys(format, &db_name, entries, v.long);
+        }
+        args::Op::Init(v) => {
+            let root = v
+                .path
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(yor::PROJECT_VAULT);
+            if root.exists() {
+                return Err(YorError::Invalid(format!(
+                    "A vault already exists in: {}",
+                    root.display()
+                )));
+            }
+            yor::init_vault(&root)?;
+            // The restored files are in plaintext, keep them out of the repository
+            fs::write(root.join(".gitignore"), "files/\n*.tmp\n")?;
+            let root = fs::canonicalize(&root).unwrap_or(root);
+            println!(
+                "Initialized an empty vault in: {}",
+                root.display().to_string().truecolor(172, 169, 138)
+            );
+        }
+        args::Op::LoadEnv(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let shell = v
+                .shell
+                .and_then(|s| yor::Shell::from_name(&s))
+                .unwrap_or_else(yor::Shell::detect);
+            for export in yor::load_env(&db_name, shell)? {
+                println!("{}", export);
+            }
+        }
+        args::Op::Exec(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            std::process::exit(yor::exec(&db_name, &v.keys, &v.maps, &v.command)?);
+        }
+        args::Op::Config(v) => match v.command {
+            args::ConfigOp::Kdf(k) => {
+                let mut params = yor::kdf_params();
+                if k.memory.is_some() || k.iterations.is_some() {
+                    params.memory = k.memory.unwrap_or(params.memory);
+                    params.iterations = k.iterations.unwrap_or(params.iterations);
+                    params
+                        .validate()
+                        .map_err(|e| YorError::Invalid(e.to_string()))?;
+                    let mut conf = yor::get_config_data()?;
+                    conf.set("kdf", &params)?;
+                    conf.save()?;
+                }
                 println!(
-                    "{}",
-                    "Database not found. Consider creating using `create`".truecolor(157, 123, 125)
+                    "Argon2i: {} iterations, {} KiB memory",
+                    params.iterations.to_string().truecolor(172, 169, 138),
+                    params.memory.to_string().truecolor(172, 169, 138)
                 );
-                std::process::exit(1);
-            });
-
-            for key in db.get_all() {
-                let db = db.get::<yor::YorData>(&key).unwrap();
-                let mut data_type = db.y_type;
-                if data_type == "bytes" {
-                    data_type = "password protected".to_string();
+            }
+            args::ConfigOp::Trash(t) => {
+                if let Some(days) = t.days {
+                    if yor::trash_age(days).is_none() {
+                        return Err(YorError::Invalid(format!(
+                            "The number of days is too large: {}",
+                            days
+                        )));
+                    }
+                    let mut conf = yor::get_config_data()?;
+                    conf.set("trash_days", &days)?;
+                    conf.save()?;
+                }
+                println!(
+                    "The trash is purged after {} days (0: never)",
+                    yor::trash_days().to_string().truecolor(172, 169, 138)
+                );
+            }
+            args::ConfigOp::History(h) => {
+                let db_name = match h.db {
+                    Some(db) => db,
+                    None => yor::get_db_name()?,
+                };
+                let mut vault = Vault::load(&db_name)?;
+                if let Some(depth) = h.depth {
+                    vault.set_history_depth(depth)?;
                 }
                 println!(
-                    "{} ({})",
-                    key.truecolor(172, 138, 172),
-                    data_type.truecolor(172, 169, 138)
+                    "Database: {} keeps {} earlier values of each key",
+                    db_name.truecolor(172, 169, 138),
+                    vault.history_depth().to_string().truecolor(172, 169, 138)
                 );
             }
+        },
+        args::Op::Gc => {
+            for db in yor::get_all_db()? {
+                // Encrypted databases are skipped if they can't be opened
+                let mut vault = match Vault::load(&db.name) {
+                    Ok(vault) => vault,
+                    Err(e) => {
+                        let message = format!("Skipping Database: {} ({})", db.name, e);
+                        eprintln!("{}", message.truecolor(157, 123, 125));
+                        continue;
+                    }
+                };
+                for key in vault.purge_expired()? {
+                    println!(
+                        "Key: {} from Database: {} is expired and removed.",
+                        key.truecolor(172, 138, 140),
+                        db.name.truecolor(172, 138, 140)
+                    );
+                }
+                vault.purge_trash(false)?;
+            }
+            yor::purge_db_trash(false)?;
+            sweep_blobs();
         }
-        args::Op::LoadEnv => yor::load_env(),
-        args::Op::LsDb => yor::print_all_db(),
-        args::Op::LsFile => yor::print_all_files(),
+        args::Op::Trash(t) => match t.command {
+            args::TrashOp::Ls(v) => {
+                let db_name = match v.db {
+                    Some(db) => db,
+                    None => yor::get_db_name()?,
+                };
+                yor::purge_db_trash(false)?;
+                // The current database may be the deleted one
+                let mut keys = Vec::new();
+                if yor::get_db_path(&db_name).exists() {
+                    let mut vault = Vault::load(&db_name)?;
+                    vault.purge_trash(false)?;
+                    keys = vault.trash();
+                }
+                output::print_trash(format, &db_name, keys, yor::get_trashed_dbs());
+            }
+            args::TrashOp::Restore(v) if v.database => {
+                yor::restore_db(&v.name)?;
+                println!("Database: {} is restored.", v.name.truecolor(172, 169, 138));
+            }
+            args::TrashOp::Restore(v) => {
+                let db_name = match v.db {
+                    Some(db) => db,
+                    None => yor::get_db_name()?,
+                };
+                Vault::load(&db_name)?.restore_removed(&v.name)?;
+                println!(
+                    "Key: {} from Database: {} is restored.",
+                    v.name.truecolor(172, 169, 138),
+                    db_name.truecolor(172, 169, 138)
+                );
+            }
+            args::TrashOp::Empty(v) => {
+                let db_name = match v.db {
+                    Some(db) => db,
+                    None => yor::get_db_name()?,
+                };
+                if !yor::confirm(
+                    format!(
+                        "Are you sure you want to empty the trash of: {} and the deleted databases? (action can't be undone)",
+                        db_name
+                    ),
+                    v.yes,
+                )? {
+                    println!("{}", "Ignoring the trash request.".truecolor(172, 138, 140));
+                    return Ok(());
+                }
+                let mut keys = 0;
+                if yor::get_db_path(&db_name).exists() {
+                    keys = Vault::load(&db_name)?.purge_trash(true)?;
+                }
+                let dbs = yor::purge_db_trash(true)?;
+                println!(
+                    "Removed {} keys and {} databases for good.",
+                    keys.to_string().truecolor(172, 138, 140),
+                    dbs.to_string().truecolor(172, 138, 140)
+                );
+                sweep_blobs();
+            }
+        },
+        args::Op::Rekey(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let mut vault = Vault::load(&db_name)?;
+
+            if !vault.prompt_unlock()? {
+                vault.unlock(&yor::get_password("[yor] current password: ")?)?;
+            }
+            let new = yor::get_new_password("[yor] new password: ")?;
+            if new.is_empty() {
+                return Err(YorError::Invalid(String::from(
+                    "The new password can't be empty.",
+                )));
+            }
+
+            let count = vault.rekey(&new, &v.keys)?;
+            println!(
+                "Rotated {} entries of Database: {}",
+                count.to_string().truecolor(172, 169, 138),
+                db_name.truecolor(172, 169, 138)
+            );
+        }
+        args::Op::Agent(v) if v.stop => {
+            yor::agent::stop()?;
+            println!("{}", "The agent is stopped.".truecolor(172, 138, 140));
+        }
+        args::Op::Agent(v) => {
+            let timeout = match v.timeout {
+                Some(timeout) => yor::parse_ttl(&timeout)? as u64,
+                None => yor::agent::DEFAULT_TIMEOUT,
+            };
+            if v.foreground {
+                return yor::agent::serve(timeout);
+            }
+            if !yor::agent::is_running() {
+                start_agent(timeout)?;
+            }
+            let status = yor::agent::status()?;
+            println!(
+                "Agent: {} (pid {}) exits after {} idle",
+                yor::agent::socket_path()?
+                    .display()
+                    .to_string()
+                    .truecolor(172, 169, 138),
+                status.pid,
+                output::duration(status.timeout as i64).truecolor(172, 169, 138)
+            );
+            for db in status.databases {
+                println!("Unlocked: {}", db.display());
+            }
+        }
+        args::Op::Unlock(v) => {
+            let db_name = match v.db {
+                Some(db) => db,
+                None => yor::get_db_name()?,
+            };
+            let mut vault = Vault::load(&db_name)?;
+            if !yor::agent::is_running() {
+                start_agent(yor::agent::DEFAULT_TIMEOUT)?;
+            }
+            vault.prompt_unlock_agent()?;
+            println!(
+                "Database: {} is unlocked in the agent.",
+                db_name.truecolor(172, 169, 138)
+            );
+        }
+        args::Op::Lock(v) => {
+            if !yor::agent::is_running() {
+                println!(
+                    "{}",
+                    "The agent is not running, nothing is unlocked.".truecolor(172, 138, 140)
+                );
+                return Ok(());
+            }
+            let db = v.db.map(|db| yor::get_db_path(&db));
+            let count = yor::agent::lock(db.as_deref())?;
+            println!(
+                "Locked {} databases.",
+                count.to_string().truecolor(172, 138, 140)
+            );
+        }
+        args::Op::LsDb => output::print_dbs(format, &describe_vault(), yor::get_all_db()?),
+        args::Op::LsFile => output::print_files(format, yor::get_all_files()?),
         args::Op::About => about(),
     }
+    Ok(())
+}
+
+/// Read the value to set: `-` reads it from stdin, `--from-file` reads the
+/// file as is, and it is prompted without echo when left out.
+fn read_value(
+    value: Option<String>,
+    from_file: Option<PathBuf>,
+    password_stdin: bool,
+) -> Result<Vec<u8>, YorError> {
+    if let Some(path) = from_file {
+        return fs::read(&path).map_err(|e| {
+            let message = format!("Cannot read the file: {} ({})", path.display(), e);
+            match e.kind() {
+                std::io::ErrorKind::NotFound => YorError::NotFound(message),
+                _ => YorError::Io(message),
+            }
+        });
+    }
+    match value.as_deref() {
+        Some("-") => {
+            if password_stdin {
+                return Err(YorError::Invalid(String::from(
+                    "The value and the passwords can't both be read from stdin.",
+                )));
+            }
+            let mut value = Vec::new();
+            std::io::stdin().read_to_end(&mut value)?;
+            Ok(value)
+        }
+        Some(value) => Ok(value.as_bytes().to_vec()),
+        None => rpassword::prompt_password("[yor] value: ")
+            .map(String::into_bytes)
+            .map_err(|_| {
+                YorError::Invalid(String::from(
+                    "Cannot prompt for the value. Give it as an argument, `-` to read stdin or --from-file.",
+                ))
+            }),
+    }
+}
+
+/// Read the fields of a record given as `name=value` (`name=-` reads the
+/// value from stdin, it is prompted without echo for `name`). Returns the
+/// name, the value and whether it was given as a secret.
+fn read_fields(
+    fields: &[String],
+    secret_fields: &[String],
+    password_stdin: bool,
+) -> Result<Vec<(String, Vec<u8>, bool)>, YorError> {
+    let given = fields
+        .iter()
+        .map(|f| (f, false))
+        .chain(secret_fields.iter().map(|f| (f, true)));
+    // stdin is read whole for the first one
+    let from_stdin = |(f, _): &(&String, bool)| f.split_once('=').is_some_and(|(_, v)| v == "-");
+    if given.clone().filter(from_stdin).count() > 1 {
+        return Err(YorError::Invalid(String::from(
+            "Only one field can be read from stdin.",
+        )));
+    }
+    let mut result = Vec::new();
+    for (field, secret) in given {
+        let (name, value) = match field.split_once('=') {
+            Some((name, value)) => (
+                name,
+                read_value(Some(value.to_string()), None, password_stdin)?,
+            ),
+            None => {
+                let value =
+                    rpassword::prompt_password(format!("[yor] {}: ", field)).map_err(|_| {
+                        YorError::Invalid(format!(
+                            "Cannot prompt for the field: {}. Give it as {}=<value>.",
+                            field, field
+                        ))
+                    })?;
+                (field.as_str(), value.into_bytes())
+            }
+        };
+        result.push((name.to_string(), value, secret));
+    }
+    Ok(result)
+}
+
+/// Parse an otp secret (base32 or otpauth:// URI) with the parameters given
+/// on the command line
+fn parse_otp(value: &[u8], args: &args::OtpArgs) -> Result<yor::otp::Otp, YorError> {
+    let value = std::str::from_utf8(value)
+        .map_err(|_| YorError::Invalid(String::from("The otp secret is not valid UTF-8.")))?;
+    let mut otp = yor::otp::Otp::parse(value)?;
+    if let Some(algorithm) = args.algorithm {
+        otp.algorithm = match algorithm {
+            args::OtpAlgorithm::Sha1 => yor::otp::OtpAlgorithm::Sha1,
+            args::OtpAlgorithm::Sha256 => yor::otp::OtpAlgorithm::Sha256,
+            args::OtpAlgorithm::Sha512 => yor::otp::OtpAlgorithm::Sha512,
+        };
+    }
+    otp.digits = args.digits.unwrap_or(otp.digits);
+    otp.period = args.period.unwrap_or(otp.period);
+    otp.counter = args.counter.or(otp.counter);
+    otp.validate()?;
+    Ok(otp)
+}
+
+/// Generate a password, or a passphrase if a number of words is given
+fn generate(gen: &args::GenArgs) -> Result<String, YorError> {
+    if let Some(words) = gen.words {
+        return yor::gen::passphrase(words, &gen.separator);
+    }
+    let classes: Vec<yor::gen::CharClass> = gen
+        .charset
+        .iter()
+        .map(|charset| match charset {
+            args::Charset::Upper => yor::gen::CharClass::Upper,
+            args::Charset::Lower => yor::gen::CharClass::Lower,
+            args::Charset::Digits => yor::gen::CharClass::Digits,
+            args::Charset::Symbols => yor::gen::CharClass::Symbols,
+        })
+        .collect();
+    yor::gen::password(gen.length, &classes, gen.no_ambiguous)
+}
+
+/// Start the agent in the background (`yor agent --foreground`) and wait
+/// for its socket
+fn start_agent(timeout: u64) -> Result<(), YorError> {
+    // Fails early if there's nowhere to put the socket
+    yor::agent::socket_path()?;
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg("--home")
+        .arg(yor::vault_root())
+        .args(["agent", "--foreground", "--timeout"])
+        .arg(format!("{}s", timeout))
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // Out of the process group of the terminal, it outlives the command
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()?;
+    for _ in 0..50 {
+        if yor::agent::is_running() {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    Err(YorError::Other(String::from("The agent didn't start.")))
+}
+
+/// Remove the files of the blob store that are no longer used, they are
+/// kept with a warning if a database can't be read
+fn sweep_blobs() {
+    match yor::sweep_blobs() {
+        Ok(0) => {}
+        Ok(count) => println!(
+            "Removed {} unused files.",
+            count.to_string().truecolor(172, 138, 140)
+        ),
+        Err(e) => {
+            let message = format!("Keeping the unused files: {}", e);
+            eprintln!("{}", message.truecolor(157, 123, 125));
+        }
+    }
+}
+
+/// Describe the active vault and where it comes from
+fn describe_vault() -> String {
+    let source = match yor::vault_source() {
+        yor::VaultSource::Flag => String::from("--home"),
+        yor::VaultSource::Env => String::from("YOR_HOME"),
+        yor::VaultSource::Project(p) => format!("project: {}", p.path.display()),
+        yor::VaultSource::Default => String::from("default"),
+    };
+    format!("vault: {} ({})", yor::vault_root().display(), source)
 }
 
 fn about() {
@@ -0,0 +1,416 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! One-time passwords (RFC 4226 HOTP and RFC 6238 TOTP).
+//!
+//! The secrets of the `otp/totp` and `otp/hotp` keys are stored encrypted as
+//! the json of an `Otp`. They are set from a base32 secret or an
+//! `otpauth://` URI (the format of the QR codes).
+
+use crate::error::YorError;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+
+/// The hash function of the HMAC
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgorithm {
+    /// Get the algorithm from its name in an `otpauth://` URI (e.g. `SHA256`)
+    pub fn from_name(name: &str) -> Option<OtpAlgorithm> {
+        match name.to_ascii_uppercase().as_str() {
+            "SHA1" => Some(OtpAlgorithm::Sha1),
+            "SHA256" => Some(OtpAlgorithm::Sha256),
+            "SHA512" => Some(OtpAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            OtpAlgorithm::Sha1 => "SHA1",
+            OtpAlgorithm::Sha256 => "SHA256",
+            OtpAlgorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// A one-time password secret and its parameters
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Otp {
+    pub secret: Vec<u8>,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+    /// The time step of a TOTP in seconds
+    pub period: u64,
+    /// The next counter of a HOTP, `None` for a TOTP
+    pub counter: Option<u64>,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+impl Otp {
+    /// A TOTP with the default parameters (SHA1, 6 digits, 30 seconds)
+    pub fn new(secret: Vec<u8>) -> Otp {
+        Otp {
+            secret,
+            algorithm: OtpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+            counter: None,
+            issuer: None,
+            account: None,
+        }
+    }
+
+    /// Parse a base32 secret (spaces and padding are ignored) or an
+    /// `otpauth://totp/...` or `otpauth://hotp/...` URI
+    pub fn parse(input: &str) -> Result<Otp, YorError> {
+        let input = input.trim();
+        let otp = if input.starts_with("otpauth://") {
+            Otp::from_uri(input)?
+        } else {
+            Otp::new(decode_base32(input)?)
+        };
+        otp.validate()?;
+        Ok(otp)
+    }
+
+    fn from_uri(uri: &str) -> Result<Otp, YorError> {
+        let invalid = |m: &str| YorError::Invalid(format!("Invalid otpauth URI: {}", m));
+        let rest = &uri["otpauth://".len()..];
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| invalid("the type is missing"))?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut secret = None;
+        let mut otp = Otp::new(Vec::new());
+        let label = percent_decode(label);
+        match label.split_once(':') {
+            Some((issuer, account)) => {
+                otp.issuer = Some(issuer.to_string());
+                otp.account = Some(account.trim().to_string());
+            }
+            None if !label.is_empty() => otp.account = Some(label),
+            None => {}
+        }
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            let number = || value.parse().map_err(|_| invalid(name));
+            match name {
+                "secret" => secret = Some(decode_base32(&value)?),
+                "issuer" => otp.issuer = Some(value.clone()),
+                "algorithm" => {
+                    otp.algorithm =
+                        OtpAlgorithm::from_name(&value).ok_or_else(|| invalid("algorithm"))?
+                }
+                "digits" => otp.digits = value.parse().map_err(|_| invalid(name))?,
+                "period" => otp.period = number()?,
+                "counter" => otp.counter = Some(number()?),
+                _ => {}
+            }
+        }
+        otp.secret = secret.ok_or_else(|| invalid("the secret is missing"))?;
+        match kind {
+            "totp" => otp.counter = None,
+            "hotp" => otp.counter = Some(otp.counter.unwrap_or_default()),
+            _ => return Err(invalid("the type must be totp or hotp")),
+        }
+        Ok(otp)
+    }
+
+    /// Check the parameters, RFC 4226 needs 6 to 10 digits
+    pub fn validate(&self) -> Result<(), YorError> {
+        if self.secret.is_empty() {
+            return Err(YorError::Invalid(String::from("The otp secret is empty.")));
+        }
+        if !(6..=10).contains(&self.digits) {
+            return Err(YorError::Invalid(String::from(
+                "The otp codes must have 6 to 10 digits.",
+            )));
+        }
+        if self.period == 0 {
+            return Err(YorError::Invalid(String::from(
+                "The otp period can't be 0 seconds.",
+            )));
+        }
+        // The counter is moved forward after each code
+        if self.counter == Some(u64::MAX) {
+            return Err(YorError::Invalid(String::from(
+                "The otp counter is too large.",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check if the counter is used instead of the time
+    pub fn is_hotp(&self) -> bool {
+        self.counter.is_some()
+    }
+
+    /// The `otpauth://` URI of the secret
+    pub fn to_uri(&self) -> String {
+        let mut label = self.account.clone().unwrap_or_default();
+        if let Some(issuer) = &self.issuer {
+            label = format!("{}:{}", issuer, label);
+        }
+        let mut uri = format!(
+            "otpauth://{}/{}?secret={}&algorithm={}&digits={}",
+            if self.is_hotp() { "hotp" } else { "totp" },
+            percent_encode(&label),
+            encode_base32(&self.secret),
+            self.algorithm.name(),
+            self.digits
+        );
+        match self.counter {
+            Some(counter) => uri.push_str(&format!("&counter={}", counter)),
+            None => uri.push_str(&format!("&period={}", self.period)),
+        }
+        if let Some(issuer) = &self.issuer {
+            uri.push_str(&format!("&issuer={}", percent_encode(issuer)));
+        }
+        uri
+    }
+
+    /// The HOTP code of the given counter (RFC 4226)
+    pub fn code(&self, counter: u64) -> String {
+        let message = counter.to_be_bytes();
+        let hash = match self.algorithm {
+            OtpAlgorithm::Sha1 => hmac::<Hmac<sha1::Sha1>>(&self.secret, &message),
+            OtpAlgorithm::Sha256 => hmac::<Hmac<sha2::Sha256>>(&self.secret, &message),
+            OtpAlgorithm::Sha512 => hmac::<Hmac<sha2::Sha512>>(&self.secret, &message),
+        };
+        // Dynamic truncation
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        let code = u64::from(binary) % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// The TOTP code at the given unix time and the number of seconds it
+    /// is valid for (RFC 6238)
+    pub fn totp(&self, time: i64) -> (String, u64) {
+        let time = time.max(0) as u64;
+        let remaining = self.period - time % self.period;
+        (self.code(time / self.period), remaining)
+    }
+}
+
+fn hmac<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Decode base32 (RFC 4648), case insensitive without spaces and padding
+fn decode_base32(input: &str) -> Result<Vec<u8>, YorError> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())
+            .ok_or_else(|| YorError::Invalid(String::from("The otp secret isn't valid base32.")))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Encode base32 (RFC 4648) without padding
+fn encode_base32(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    output
+}
+
+fn percent_decode(input: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut chars = input.bytes();
+    while let Some(b) = chars.next() {
+        match b {
+            b'%' => {
+                let hex: Vec<u8> = chars.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(byte) => bytes.push(byte),
+                    None => {
+                        bytes.push(b'%');
+                        bytes.extend(hex);
+                    }
+                }
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' | b'@' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        let otp = Otp::new(b"12345678901234567890".to_vec());
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(otp.code(counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn totp_rfc6238_vectors() {
+        let secrets = [
+            (OtpAlgorithm::Sha1, &b"12345678901234567890"[..]),
+            (
+                OtpAlgorithm::Sha256,
+                &b"12345678901234567890123456789012"[..],
+            ),
+            (
+                OtpAlgorithm::Sha512,
+                &b"1234567890123456789012345678901234567890123456789012345678901234"[..],
+            ),
+        ];
+        let expected: [(i64, [&str; 3]); 6] = [
+            (59, ["94287082", "46119246", "90693936"]),
+            (1111111109, ["07081804", "68084774", "25091201"]),
+            (1111111111, ["14050471", "67062674", "99943326"]),
+            (1234567890, ["89005924", "91819424", "93441116"]),
+            (2000000000, ["69279037", "90698825", "38618901"]),
+            (20000000000, ["65353130", "77737706", "47863826"]),
+        ];
+        for (i, (algorithm, secret)) in secrets.into_iter().enumerate() {
+            let mut otp = Otp::new(secret.to_vec());
+            otp.algorithm = algorithm;
+            otp.digits = 8;
+            for (time, codes) in expected {
+                assert_eq!(otp.totp(time).0, codes[i], "{:?} at {}", algorithm, time);
+            }
+        }
+        assert_eq!(Otp::new(b"secret".to_vec()).totp(59).1, 1);
+    }
+
+    #[test]
+    fn base32_rfc4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(encode_base32(plain.as_bytes()), encoded);
+            assert_eq!(decode_base32(encoded).unwrap(), plain.as_bytes());
+        }
+        // Padding, spaces and lowercase are accepted
+        assert_eq!(decode_base32("mzxw 6yq=").unwrap(), b"foob");
+        assert!(decode_base32("MZXW1").is_err());
+    }
+
+    #[test]
+    fn otpauth_uri() {
+        let otp = Otp::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(otp.secret, decode_base32("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(otp.account.as_deref(), Some("john@example.com"));
+        assert_eq!(otp.algorithm, OtpAlgorithm::Sha256);
+        assert_eq!((otp.digits, otp.period, otp.counter), (8, 60, None));
+        let parsed = Otp::parse(&otp.to_uri()).unwrap();
+        assert_eq!(parsed.to_uri(), otp.to_uri());
+
+        let hotp = Otp::parse("otpauth://hotp/bob?secret=JBSWY3DPEHPK3PXP&counter=7").unwrap();
+        assert_eq!(hotp.counter, Some(7));
+        assert_eq!(hotp.account.as_deref(), Some("bob"));
+        let hotp = Otp::parse("otpauth://hotp/bob?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(hotp.counter, Some(0));
+
+        for invalid in [
+            "otpauth://totp/bob?issuer=ACME",
+            "otpauth://motp/bob?secret=JBSWY3DPEHPK3PXP",
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP&algorithm=MD5",
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP&digits=5",
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP&digits=11",
+            // 2^32 + 6 doesn't wrap around to 6
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP&digits=4294967302",
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP&period=0",
+            "otpauth://hotp/bob?secret=JBSWY3DPEHPK3PXP&counter=18446744073709551615",
+        ] {
+            assert!(Otp::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,438 @@
+/*
+ *
+ *  Copyright (C) 2022-present riyuzenn
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+use crate::args::Format;
+use chrono::{Local, SecondsFormat, TimeZone, Utc};
+use colored::Colorize;
+use serde_json::json;
+use std::io::{self, IsTerminal, Write};
+use yor::{DbInfo, RecordFields, TrashedDb, TrashedKey, YorData, YorHistory};
+
+/// Resolve the output format, the default one is `table` on a terminal and
+/// `plain` otherwise. Colors are only used by `table` on a terminal without
+/// `NO_COLOR`.
+pub fn resolve_format(format: Option<Format>, json: bool) -> Format {
+    let tty = std::io::stdout().is_terminal();
+    let format = match (json, format) {
+        (true, _) => Format::Json,
+        (false, Some(format)) => format,
+        (false, None) if tty => Format::Table,
+        (false, None) => Format::Plain,
+    };
+    if format != Format::Table || !tty || std::env::var_os("NO_COLOR").is_some() {
+        colored::control::set_override(false);
+    }
+    format
+}
+
+/// Print the keys of a database, `long` adds the times and the note
+pub fn print_keys(format: Format, db_name: &str, entries: Vec<(String, YorData)>, long: bool) {
+    match format {
+        Format::Table => {
+            for (key, data) in entries {
+                let mut data_type = data.y_type.clone();
+                if data.is_encrypted() {
+                    data_type.push_str(", password protected");
+                }
+                let mut line = format!(
+                    "{} ({})",
+                    key.truecolor(172, 138, 172),
+                    data_type.truecolor(172, 169, 138)
+                );
+                if !data.tags.is_empty() {
+                    let tags = format!(" [{}]", data.tags.join(", "));
+                    line.push_str(&tags.truecolor(138, 152, 172).to_string());
+                }
+                if data.is_expired() {
+                    line.push_str(&" (expired)".truecolor(157, 123, 125).to_string());
+                } else if let Some(expires_at) = data
+                    .expires_at
+                    .filter(|_| data.expires_within(EXPIRY_WARNING))
+                {
+                    let expires = format!(" (expires in {})", duration(expires_at - yor::now()));
+                    line.push_str(&expires.truecolor(164, 141, 110).to_string());
+                }
+                println!("{}", line);
+                if long {
+                    let mut details = format!(
+                        "    created {} | updated {} | accessed {}",
+                        local_time(data.created_at),
+                        local_time(data.updated_at),
+                        local_time(data.last_accessed)
+                    );
+                    if data.expires_at.is_some() {
+                        details.push_str(&format!(" | expires {}", local_time(data.expires_at)));
+                    }
+                    if let Some(note) = &data.note {
+                        details.push_str(&format!(" | {}", note));
+                    }
+                    println!("{}", details.truecolor(138, 152, 172));
+                }
+            }
+        }
+        Format::Plain => {
+            for (key, data) in entries {
+                // `expired`, or the expiry time if the key expires
+                let expiry = if data.is_expired() {
+                    "expired".to_string()
+                } else {
+                    utc_time(data.expires_at)
+                };
+                let mut line = format!(
+                    "{}\t{}\t{}\t{}",
+                    key,
+                    data.y_type,
+                    data.is_encrypted(),
+                    expiry
+                );
+                if long {
+                    line.push_str(&format!(
+                        "\t{}\t{}\t{}\t{}\t{}\t{}",
+                        utc_time(data.created_at),
+                        utc_time(data.updated_at),
+                        utc_time(data.last_accessed),
+                        utc_time(data.expires_at),
+                        data.tags.join(","),
+                        data.note.as_deref().unwrap_or_default()
+                    ));
+                }
+                println!("{}", line);
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = entries
+                .into_iter()
+                .map(|(key, data)| {
+                    json!({
+                        "key": key,
+                        "type": data.y_type,
+                        "encrypted": data.is_encrypted(),
+                        "database": db_name,
+                        "created_at": data.created_at,
+                        "updated_at": data.updated_at,
+                        "last_accessed": data.last_accessed,
+                        "expires_at": data.expires_at,
+                        "expired": data.is_expired(),
+                        "tags": data.tags,
+                        "note": data.note,
+                    })
+                })
+                .collect();
+            println!("{}", json!(records));
+        }
+    }
+}
+
+/// Print the earlier values of a key, numbered from 1 for `rollback --to`
+pub fn print_history(format: Format, db_name: &str, key: &str, history: Vec<YorHistory>) {
+    match format {
+        Format::Table => {
+            for (i, earlier) in history.into_iter().enumerate() {
+                let mut data_type = earlier.y_type;
+                if earlier.y_data.is_encrypted() {
+                    data_type.push_str(", password protected");
+                }
+                println!(
+                    "{} {} ({}) set {} | replaced {}",
+                    format!("#{}", i + 1).truecolor(172, 138, 172),
+                    key.truecolor(172, 138, 172),
+                    data_type.truecolor(172, 169, 138),
+                    local_time(earlier.updated_at),
+                    local_time(Some(earlier.replaced_at))
+                );
+            }
+        }
+        Format::Plain => {
+            for (i, earlier) in history.into_iter().enumerate() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    i + 1,
+                    earlier.y_type,
+                    earlier.y_data.is_encrypted(),
+                    utc_time(earlier.updated_at),
+                    utc_time(Some(earlier.replaced_at))
+                );
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = history
+                .into_iter()
+                .enumerate()
+                .map(|(i, earlier)| {
+                    json!({
+                        "number": i + 1,
+                        "key": key,
+                        "type": earlier.y_type,
+                        "encrypted": earlier.y_data.is_encrypted(),
+                        "database": db_name,
+                        "updated_at": earlier.updated_at,
+                        "replaced_at": earlier.replaced_at,
+                    })
+                })
+                .collect();
+            println!("{}", json!(records));
+        }
+    }
+}
+
+/// Print the fields of a record, the masked ones (`None`) are shown as stars
+/// or `null` in json
+pub fn print_record(format: Format, db_name: &str, key: &str, fields: RecordFields) {
+    const MASK: &str = "********";
+    let fields = fields.into_iter().map(|(field, value)| {
+        let value = value.map(|v| String::from_utf8_lossy(&v).into_owned());
+        (field, value)
+    });
+    match format {
+        Format::Table => {
+            for (field, value) in fields {
+                println!(
+                    "{}: {}",
+                    field.name.truecolor(172, 138, 172),
+                    value.as_deref().unwrap_or(MASK).truecolor(138, 172, 171)
+                );
+            }
+        }
+        Format::Plain => {
+            for (field, value) in fields {
+                println!("{}\t{}", field.name, value.as_deref().unwrap_or(MASK));
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = fields
+                .map(|(field, value)| {
+                    json!({
+                        "name": field.name,
+                        "value": value,
+                        "sensitive": field.sensitive,
+                    })
+                })
+                .collect();
+            println!(
+                "{}",
+                json!({
+                    "key": key,
+                    "fields": records,
+                    "database": db_name,
+                })
+            );
+        }
+    }
+}
+
+/// Print the code of an otp secret, with the seconds left for a TOTP
+pub fn print_otp(format: Format, db_name: &str, key: &str, code: &str, remaining: Option<u64>) {
+    match format {
+        Format::Table => match remaining {
+            Some(remaining) => println!(
+                "{} {}",
+                code.truecolor(138, 172, 171),
+                format!("({}s left)", remaining).truecolor(138, 152, 172)
+            ),
+            None => println!("{}", code.truecolor(138, 172, 171)),
+        },
+        Format::Plain => match remaining {
+            Some(remaining) => println!("{}\t{}", code, remaining),
+            None => println!("{}", code),
+        },
+        Format::Json => println!(
+            "{}",
+            json!({
+                "key": key,
+                "code": code,
+                "remaining": remaining,
+                "database": db_name,
+            })
+        ),
+    }
+}
+
+/// Print the removed keys of a database and the deleted databases
+pub fn print_trash(format: Format, db_name: &str, keys: Vec<TrashedKey>, dbs: Vec<TrashedDb>) {
+    match format {
+        Format::Table => {
+            for trashed in keys {
+                println!(
+                    "{} ({}) removed {}",
+                    trashed.key.truecolor(172, 138, 172),
+                    format!("key of {}", db_name).truecolor(172, 169, 138),
+                    local_time(Some(trashed.removed_at))
+                );
+            }
+            for db in dbs {
+                println!(
+                    "{} ({}) removed {}",
+                    db.name.truecolor(172, 138, 172),
+                    "database".truecolor(172, 169, 138),
+                    local_time(Some(db.removed_at))
+                );
+            }
+        }
+        Format::Plain => {
+            for trashed in keys {
+                println!(
+                    "key\t{}\t{}\t{}",
+                    trashed.key,
+                    db_name,
+                    utc_time(Some(trashed.removed_at))
+                );
+            }
+            for db in dbs {
+                println!("database\t{}\t\t{}", db.name, utc_time(Some(db.removed_at)));
+            }
+        }
+        Format::Json => {
+            let keys = keys.into_iter().map(|trashed| {
+                json!({
+                    "kind": "key",
+                    "name": trashed.key,
+                    "database": db_name,
+                    "removed_at": trashed.removed_at,
+                })
+            });
+            let dbs = dbs.into_iter().map(|db| {
+                json!({
+                    "kind": "database",
+                    "name": db.name,
+                    "removed_at": db.removed_at,
+                })
+            });
+            let records: Vec<_> = keys.chain(dbs).collect();
+            println!("{}", json!(records));
+        }
+    }
+}
+
+/// Keys expiring within this number of seconds are marked by `ls`
+const EXPIRY_WARNING: i64 = 24 * 60 * 60;
+
+/// Format a number of seconds for humans, e.g. `5h` or `12m`
+pub fn duration(seconds: i64) -> String {
+    match seconds {
+        s if s >= 24 * 60 * 60 => format!("{}d", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s.max(0)),
+    }
+}
+
+/// Format unix seconds in the local time zone for humans
+fn local_time(time: Option<i64>) -> String {
+    match time.and_then(|t| Local.timestamp_opt(t, 0).single()) {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// Format unix seconds as RFC 3339 in UTC, empty if unknown
+fn utc_time(time: Option<i64>) -> String {
+    time.and_then(|t| Utc.timestamp_opt(t, 0).single())
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Print the databases of the vault, the table starts with a description of
+/// the vault
+pub fn print_dbs(format: Format, description: &str, dbs: Vec<DbInfo>) {
+    match format {
+        Format::Table => {
+            println!("{}", description.truecolor(138, 152, 172));
+            for db in dbs {
+                let mut db_name = db.name;
+                if db.encrypted {
+                    db_name.push_str(&" (encrypted)".truecolor(138, 152, 172).to_string());
+                }
+                if db.current {
+                    db_name.push_str(&" (current)".truecolor(164, 141, 110).to_string());
+                }
+                println!("{}", db_name.truecolor(172, 138, 172));
+            }
+        }
+        Format::Plain => {
+            for db in dbs {
+                println!("{}\t{}\t{}", db.name, db.encrypted, db.current);
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = dbs
+                .into_iter()
+                .map(|db| {
+                    json!({
+                        "name": db.name,
+                        "encrypted": db.encrypted,
+                        "current": db.current,
+                        "vault": yor::vault_root(),
+                    })
+                })
+                .collect();
+            println!("{}", json!(records));
+        }
+    }
+}
+
+/// Print the files of the file environment
+pub fn print_files(format: Format, files: Vec<String>) {
+    match format {
+        Format::Table => {
+            for filename in files {
+                println!("{}", filename.truecolor(172, 138, 172));
+            }
+        }
+        Format::Plain => {
+            for filename in files {
+                println!("{}", filename);
+            }
+        }
+        Format::Json => println!("{}", json!(files)),
+    }
+}
+
+/// Print the value of a key (or the path of the restored file). Values that
+/// aren't valid UTF-8 are written as is, or base64 encoded in json.
+pub fn print_value(format: Format, db_name: &str, key: &str, value: Vec<u8>) -> io::Result<()> {
+    let value = match String::from_utf8(value) {
+        Ok(value) => value,
+        Err(e) if format == Format::Json => {
+            println!(
+                "{}",
+                json!({
+                    "key": key,
+                    "value": base64::encode(e.as_bytes()),
+                    "encoding": "base64",
+                    "database": db_name,
+                })
+            );
+            return Ok(());
+        }
+        Err(e) => return io::stdout().write_all(e.as_bytes()),
+    };
+    match format {
+        Format::Table => println!("{}", value.truecolor(138, 172, 171)),
+        Format::Plain => println!("{}", value),
+        Format::Json => println!(
+            "{}",
+            json!({
+                "key": key,
+                "value": value,
+                "database": db_name,
+            })
+        ),
+    }
+    Ok(())
+}
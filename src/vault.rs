@@ -0,0 +1,1077 @@
+/*
+ *
+ *  Copyright (c) 2022-present riyuzenn
+ *
+ *  this program is free software: you can redistribute it and/or modify
+ *  it under the terms of the gnu general public license as published by
+ *  the free software foundation, either version 3 of the license, or
+ *  (at your option) any later version.
+ *
+ *  this program is distributed in the hope that it will be useful,
+ *  but without any warranty; without even the implied warranty of
+ *  merchantability or fitness for a particular purpose.  see the
+ *  gnu general public license for more details.
+ *
+ *  you should have received a copy of the gnu general public license
+ *  along with this program.  if not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! A yor database with the password of its protected values.
+//!
+//! Nothing is printed or prompted by the `Vault` methods except the `prompt_*`
+//! ones, which go through the password source of the process (see
+//! `set_password_source`).
+
+use crate::agent;
+use crate::blob;
+use crate::error::YorError;
+use crate::otp::Otp;
+use crate::yor::{
+    agent_key, create_db, get_db_path, get_keys, get_meta, get_trash, is_outdated, is_reserved_key,
+    is_sealed_db, is_trash_expired, kdf_params, load_db, load_db_with_password, lock_db_file, now,
+    rekey, reload_db, salt, set_db_id, set_db_key, set_key_salt, split_type, verify_db_key,
+    with_password, AssociatedData, DbKey, RecordFields, TrashedKey, YorData, YorDataType, YorDb,
+    YorField, YorHistory, DEFAULT_HISTORY, FILE_TYPES, META_KEY, RECORD_KINDS, RESERVED_PREFIX,
+    TRASH_KEY,
+};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub struct Vault {
+    db: YorDb,
+    key: Option<DbKey>,
+}
+
+/// The note, tags and expiry stored with a new value, see `Vault::set_value`
+#[derive(Default, Clone)]
+pub struct EntryDetails {
+    /// The note of the key, an empty one removes it
+    pub note: Option<String>,
+    /// The tags to add to the key
+    pub tags: Vec<String>,
+    /// When the value expires (unix seconds), `None` never
+    pub expires_at: Option<i64>,
+}
+
+impl Vault {
+    /// Open the database at the given path. Encrypted databases need the
+    /// password, it is checked against the master password if there's one.
+    /// Without a master password, the password is used for the protected
+    /// values.
+    ///
+    /// # Arguments
+    /// - `path` - The path of the database
+    /// - `password` - The password of the database, if any
+    pub fn open(path: impl AsRef<Path>, password: Option<&str>) -> Result<Vault, YorError> {
+        let path = path.as_ref();
+        let db = match password {
+            Some(password) => load_db_with_password(path, password)?,
+            None if is_sealed_db(path) => return Err(YorError::WrongPassword),
+            None => load_db(path)?,
+        };
+        let mut vault = Vault::from_db(db)?;
+        if let Some(password) = password {
+            vault.unlock(password)?;
+        }
+        Ok(vault)
+    }
+
+    /// Open the database of the environment with the given name. The password
+    /// of an encrypted database is prompted (see `with_password`).
+    pub fn load(db_name: &str) -> Result<Vault, YorError> {
+        Vault::from_db(load_db(&get_db_path(db_name))?)
+    }
+
+    /// Create a new database at the given path.
+    ///
+    /// # Arguments
+    /// - `path` - The path of the database, it must not exist
+    /// - `password` - The master password of the database, if any
+    /// - `encrypted` - Encrypt the whole database with the master password
+    pub fn create(
+        path: impl AsRef<Path>,
+        password: Option<&str>,
+        encrypted: bool,
+    ) -> Result<Vault, YorError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(YorError::Invalid(format!(
+                "It looks like database: {} is already created.",
+                path.display()
+            )));
+        }
+        let password = password.filter(|p| !p.is_empty());
+        if encrypted && password.is_none() {
+            return Err(YorError::Invalid(String::from(
+                "An encrypted database needs a master password.",
+            )));
+        }
+
+        let key = password
+            .map(|password| Ok::<_, YorError>(DbKey::new(password, &salt()?, kdf_params())))
+            .transpose()?;
+        let seal_key = key.clone().filter(|_| encrypted);
+        let mut db = create_db(&path.to_string_lossy(), seal_key);
+        set_db_id(&mut db)?;
+        let mut vault = Vault { db, key: None };
+        if let Some(key) = key {
+            set_db_key(&mut vault.db, &key)?;
+            vault.set_key(key)?;
+        }
+        vault.db.save()?;
+        Ok(vault)
+    }
+
+    fn from_db(mut db: YorDb) -> Result<Vault, YorError> {
+        // Stored with the next save, nothing is encrypted for the id before
+        set_db_id(&mut db)?;
+        let mut vault = Vault { db, key: None };
+        // The key of an encrypted database is its database key
+        if let Some(key) = vault.db.seal_key().cloned() {
+            vault.set_key(key)?;
+        }
+        Ok(vault)
+    }
+
+    /// Use the given key for the protected values, the salt of the database
+    /// key is stored with the next save if the database has none yet. The
+    /// key of a master password is checked before, an older check value
+    /// bound to the name of the database is written again.
+    fn set_key(&mut self, key: DbKey) -> Result<(), YorError> {
+        set_key_salt(&mut self.db, &key)?;
+        if get_meta(&self.db).db_key.is_some_and(is_outdated) {
+            set_db_key(&mut self.db, &key)?;
+        }
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// The underlying database
+    pub fn db(&self) -> &YorDb {
+        &self.db
+    }
+
+    /// Check if the protected values are encrypted with a master password
+    pub fn has_master_password(&self) -> bool {
+        get_meta(&self.db).db_key.is_some()
+    }
+
+    /// Check if a password is set to read and write the protected values
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Set the password of the protected values, it must be the master
+    /// password if the database has one. The database key is derived once
+    /// for all the values.
+    pub fn unlock(&mut self, password: &str) -> Result<(), YorError> {
+        let key = DbKey::for_db(&self.db, password)?;
+        if self.has_master_password() && !verify_db_key(&self.db, &key) {
+            return Err(YorError::WrongPassword);
+        }
+        self.set_key(key)
+    }
+
+    /// Forget the password, the values set afterwards aren't encrypted
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    /// Prompt for the master password if the database has one and the vault
+    /// is locked. Returns `false` if the database has no master password.
+    pub fn prompt_unlock(&mut self) -> Result<bool, YorError> {
+        if self.is_unlocked() {
+            return Ok(true);
+        }
+        if !self.has_master_password() {
+            return Ok(false);
+        }
+        let check = |password: &str| {
+            DbKey::for_db(&self.db, password)
+                .ok()
+                .filter(|key| verify_db_key(&self.db, key))
+        };
+        let agent_key = agent_key(&self.db).filter(|key| verify_db_key(&self.db, key));
+        let key = match agent_key {
+            Some(key) => key,
+            None => with_password("[yor] master password for the database: ", check)?,
+        };
+        self.set_key(key)?;
+        Ok(true)
+    }
+
+    /// Prompt for the password needed to read the given key, nothing is
+    /// prompted if the key isn't encrypted or the vault is unlocked.
+    pub fn prompt_unlock_key(&mut self, key: &str) -> Result<(), YorError> {
+        if !self.entry(key)?.is_encrypted() || self.prompt_unlock()? {
+            return Ok(());
+        }
+        let check = |password: &str| {
+            DbKey::for_db(&self.db, password)
+                .ok()
+                .filter(|db_key| self.check_key(key, db_key))
+        };
+        // The key given to the agent is stale after a rekey
+        let agent_key = agent_key(&self.db).filter(|db_key| self.check_key(key, db_key));
+        let db_key = match agent_key {
+            Some(db_key) => db_key,
+            None => with_password("[yor] password for the key: ", check)?,
+        };
+        self.set_key(db_key)
+    }
+
+    /// Prompt for the password of the protected values (the master password
+    /// if there's one) and hand the database key to the agent, see `yor
+    /// unlock`. Without a master password, it is checked against an encrypted
+    /// key if there's one. The database is saved, so the salt of the key is
+    /// stored.
+    pub fn prompt_unlock_agent(&mut self) -> Result<(), YorError> {
+        if !self.prompt_unlock()? {
+            let key = self
+                .iter()
+                .find(|(_, yor)| yor.is_encrypted())
+                .map(|(key, _)| key);
+            let db_key = with_password("[yor] password for the keys: ", |password| {
+                DbKey::for_db(&self.db, password)
+                    .ok()
+                    .filter(|db_key| key.as_ref().is_none_or(|key| self.check_key(key, db_key)))
+            })?;
+            self.set_key(db_key)?;
+        }
+        self.db.save()?;
+        let key = self.key()?;
+        agent::add(
+            self.db.path(),
+            key.salt(),
+            &key.params(),
+            &key.derived_key()?,
+        )
+    }
+
+    /// Check if the given key can be read with the password
+    pub fn check_password(&self, key: &str, password: &str) -> bool {
+        DbKey::for_db(&self.db, password).is_ok_and(|db_key| self.check_key(key, &db_key))
+    }
+
+    /// Check if the given key can be read with the database key
+    fn check_key(&self, key: &str, db_key: &DbKey) -> bool {
+        match self.entry(key) {
+            Ok(yor) => can_open(&yor.y_data, db_key, &self.ad(key, &yor.y_type)),
+            Err(_) => false,
+        }
+    }
+
+    /// All the keys of the database, sorted
+    pub fn list(&self) -> Vec<String> {
+        get_keys(&self.db)
+    }
+
+    /// Iterate over the entries of the database, sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (String, YorData)> + '_ {
+        self.list()
+            .into_iter()
+            .filter_map(|key| self.db.get::<YorData>(&key).map(|yor| (key, yor)))
+    }
+
+    /// Check if the given key exists
+    pub fn contains(&self, key: &str) -> bool {
+        !is_reserved_key(key) && self.db.exists(key)
+    }
+
+    /// Get the stored entry of the given key, the data isn't decrypted
+    pub fn entry(&self, key: &str) -> Result<YorData, YorError> {
+        if !self.contains(key) {
+            return Err(YorError::NotFound(format!(
+                "Key: {} not found, perhaps it doesn't exist at all?",
+                key
+            )));
+        }
+        self.db
+            .get::<YorData>(key)
+            .ok_or_else(|| YorError::Corrupt(format!("Cannot read the key: {}", key)))
+    }
+
+    /// Get the stored entry of the given key, unless it is expired
+    fn live_entry(&self, key: &str) -> Result<YorData, YorError> {
+        let yor = self.entry(key)?;
+        if yor.is_expired() {
+            return Err(YorError::NotFound(format!(
+                "Key: {} is expired, see `yor gc`",
+                key
+            )));
+        }
+        Ok(yor)
+    }
+
+    fn key(&self) -> Result<&DbKey, YorError> {
+        self.key.as_ref().ok_or(YorError::WrongPassword)
+    }
+
+    /// The key of the new protected values, `None` when they aren't encrypted
+    fn encryption_key(&self) -> Option<&DbKey> {
+        self.key.as_ref().filter(|key| !key.is_empty())
+    }
+
+    /// The associated data of the values of the given key, see
+    /// `AssociatedData`
+    fn ad(&self, key: &str, y_type: &str) -> AssociatedData {
+        AssociatedData::new(&self.db, key, y_type)
+    }
+
+    /// Decrypt a value of the given key. The master password is checked when
+    /// the vault is unlocked, so a value that doesn't decrypt with it was
+    /// tampered with or moved from another key.
+    fn decrypt(&self, key: &str, data: &[u8], ad: &AssociatedData) -> Result<Vec<u8>, YorError> {
+        match self.key()?.decrypt(data, ad) {
+            Err(YorError::WrongPassword) if self.has_master_password() => {
+                Err(YorError::Corrupt(format!(
+                    "The value of the key: {} doesn't belong to it, it was tampered with",
+                    key
+                )))
+            }
+            result => result,
+        }
+    }
+
+    /// Get the value of the given key, decrypted with the password of the
+    /// vault. Files are read whole, see `restore` for large files.
+    pub fn get(&self, key: &str) -> Result<Vec<u8>, YorError> {
+        let yor = self.live_entry(key)?;
+        match yor.y_data {
+            YorDataType::Bytes(d) => self.decrypt(key, &d, &self.ad(key, &yor.y_type)),
+            YorDataType::Blob(b) => {
+                let ad = self.ad(key, &yor.y_type);
+                let key = if b.encrypted { Some(self.key()?) } else { None };
+                let mut data = Vec::new();
+                blob::open_blob(&b, key, &ad)?.read_to_end(&mut data)?;
+                Ok(data)
+            }
+            // Files were stored as base64 before the blob store
+            YorDataType::Str(d) if yor.is_file() => base64::decode(d).map_err(|_| {
+                YorError::Corrupt(format!("The file of the key: {} is corrupted", key))
+            }),
+            YorDataType::Str(d) => Ok(d.into_bytes()),
+            YorDataType::Binary(d) => Ok(d),
+            // Records are read as a json object of their fields
+            YorDataType::Record(fields) => {
+                let mut record = serde_json::Map::new();
+                for field in &fields {
+                    let value = self.open_field(key, &yor.y_type, field)?;
+                    let value = String::from_utf8_lossy(&value).into_owned();
+                    record.insert(field.name.clone(), value.into());
+                }
+                Ok(serde_json::to_vec(&record)?)
+            }
+        }
+    }
+
+    /// Get the value of the given key as a string
+    pub fn get_string(&self, key: &str) -> Result<String, YorError> {
+        String::from_utf8(self.get(key)?).map_err(|_| {
+            YorError::Invalid(format!("The value of the key: {} is not valid UTF-8", key))
+        })
+    }
+
+    /// Write the value of the given key to a file, files are streamed from the
+    /// blob store so their size doesn't matter.
+    pub fn restore(&self, key: &str, path: &Path) -> Result<(), YorError> {
+        let yor = self.live_entry(key)?;
+        match &yor.y_data {
+            YorDataType::Blob(b) => {
+                let ad = self.ad(key, &yor.y_type);
+                let key = if b.encrypted { Some(self.key()?) } else { None };
+                Ok(blob::restore_blob(b, key, &ad, path)?)
+            }
+            _ => fs::write(path, self.get(key)?).map_err(|e| {
+                YorError::Io(format!("Cannot write the file: {} ({})", path.display(), e))
+            }),
+        }
+    }
+
+    /// Set the value of the given key as `data/str`, see `set_value`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), YorError> {
+        self.set_value(key, value.as_bytes(), "data/str", &EntryDetails::default())
+    }
+
+    /// Update or insert the given key and save the database. The value is
+    /// encrypted when the vault is unlocked, it can be any bytes.
+    ///
+    /// # Arguments
+    /// - `key` - The key to set
+    /// - `value` - The value
+    /// - `y_type` - The type of the value: `data/<extension>`
+    /// - `details` - The note, tags and expiry of the value
+    pub fn set_value(
+        &mut self,
+        key: &str,
+        value: &[u8],
+        y_type: &str,
+        details: &EntryDetails,
+    ) -> Result<(), YorError> {
+        let splitted_type = check_type(key, y_type)?;
+        if splitted_type[0] == "otp" {
+            return Err(YorError::Invalid(String::from(
+                "The otp secrets are set with `set_otp`",
+            )));
+        }
+        if splitted_type[0] == "record" {
+            return Err(YorError::Invalid(String::from(
+                "The records are set with `set_fields`",
+            )));
+        }
+        if splitted_type[0] != "data" {
+            return Err(YorError::Invalid(format!(
+                "The value of a {} is the path of the file to store",
+                y_type
+            )));
+        }
+
+        let mut y_type = y_type.to_string();
+        let data = match self.encryption_key() {
+            Some(db_key) => {
+                if splitted_type[1] == "str" {
+                    y_type = String::from("data/byte");
+                }
+                YorDataType::Bytes(db_key.encrypt(value, &self.ad(key, &y_type))?)
+            }
+            None => match String::from_utf8(value.to_vec()) {
+                Ok(value) => YorDataType::Str(value),
+                Err(e) => {
+                    if splitted_type[1] == "str" {
+                        y_type = String::from("data/byte");
+                    }
+                    YorDataType::Binary(e.into_bytes())
+                }
+            },
+        };
+        self.insert(key, YorData::new(data, y_type), details)
+    }
+
+    /// Store the given file in the blob store (encrypted when the vault is
+    /// unlocked) and set the key to it.
+    ///
+    /// # Arguments
+    /// - `key` - The key to set
+    /// - `path` - The path of the file to store
+    /// - `y_type` - The type of the file: `<file|image|video>/<extension>`
+    /// - `details` - The note, tags and expiry of the file
+    pub fn set_file(
+        &mut self,
+        key: &str,
+        path: &Path,
+        y_type: &str,
+        details: &EntryDetails,
+    ) -> Result<(), YorError> {
+        let splitted_type = check_type(key, y_type)?;
+        if !FILE_TYPES.contains(&splitted_type[0]) {
+            return Err(YorError::Invalid(format!(
+                "The type of a file must be one of: {}",
+                FILE_TYPES.join(", ")
+            )));
+        }
+
+        // Files are streamed to the blob store, the database only keeps a reference
+        let file = fs::File::open(path).map_err(|e| {
+            YorError::NotFound(format!("Cannot read the file: {} ({})", path.display(), e))
+        })?;
+        let ad = self.ad(key, y_type);
+        let data = YorDataType::Blob(blob::store_blob(file, self.encryption_key(), &ad)?);
+        self.insert(key, YorData::new(data, y_type.to_string()), details)
+    }
+
+    /// Set the fields of the record of the given key and save the database.
+    /// Sensitive fields are encrypted when the vault is unlocked, an empty
+    /// value removes the field. The other fields of the record are kept.
+    ///
+    /// # Arguments
+    /// - `key` - The key of the record
+    /// - `kind` - The kind of the record (see `RECORD_KINDS`), needed to
+    ///   create it
+    /// - `fields` - The name, value and sensitivity of the fields to set
+    /// - `details` - The note, tags and expiry of the record
+    pub fn set_fields(
+        &mut self,
+        key: &str,
+        kind: Option<&str>,
+        fields: &[(String, Vec<u8>, bool)],
+        details: &EntryDetails,
+    ) -> Result<(), YorError> {
+        let (y_type, mut record) = match self.db.get::<YorData>(key) {
+            Some(YorData {
+                y_data: YorDataType::Record(record),
+                y_type,
+                ..
+            }) => (y_type, record),
+            Some(_) => return Err(YorError::Invalid(format!("Key: {} is not a record", key))),
+            None => {
+                let kind = kind.ok_or_else(|| {
+                    YorError::Invalid(format!(
+                        "The kind of the record is needed to create it (--type record/<{}>)",
+                        RECORD_KINDS.map(|(k, _)| k).join("|")
+                    ))
+                })?;
+                (format!("record/{}", kind), Vec::new())
+            }
+        };
+        if let Some(kind) = kind {
+            if y_type != format!("record/{}", kind) {
+                return Err(YorError::Invalid(format!(
+                    "Key: {} is a {} record",
+                    key,
+                    split_type(&y_type)[1]
+                )));
+            }
+        }
+        check_type(key, &y_type)?;
+        if !RECORD_KINDS
+            .iter()
+            .any(|(k, _)| *k == split_type(&y_type)[1])
+        {
+            return Err(YorError::Invalid(format!(
+                "The kind of a record must be one of: {}",
+                RECORD_KINDS.map(|(k, _)| k).join(", ")
+            )));
+        }
+
+        for (name, value, sensitive) in fields {
+            if name.is_empty() || name.contains(['.', '=']) {
+                return Err(YorError::Invalid(format!(
+                    "Invalid field name: {} (it can't contain `.` or `=`)",
+                    name
+                )));
+            }
+            record.retain(|f| &f.name != name);
+            if value.is_empty() {
+                continue;
+            }
+            let data = match self.encryption_key() {
+                Some(db_key) if *sensitive => {
+                    let ad = self.ad(key, &y_type).field(name);
+                    YorDataType::Bytes(db_key.encrypt(value, &ad)?)
+                }
+                _ => match String::from_utf8(value.clone()) {
+                    Ok(value) => YorDataType::Str(value),
+                    Err(e) => YorDataType::Binary(e.into_bytes()),
+                },
+            };
+            record.push(YorField {
+                name: name.clone(),
+                data,
+                sensitive: *sensitive,
+            });
+        }
+        self.insert(
+            key,
+            YorData::new(YorDataType::Record(record), y_type),
+            details,
+        )
+    }
+
+    /// Split `<record>.<field>` into the key of a record and a field name,
+    /// `None` if the key exists or isn't a field of a record
+    pub fn split_field<'a>(&self, key: &'a str) -> Option<(&'a str, &'a str)> {
+        if self.contains(key) {
+            return None;
+        }
+        let (record, field) = key.rsplit_once('.')?;
+        self.entry(record)
+            .ok()
+            .filter(|yor| yor.is_record())
+            .map(|_| (record, field))
+    }
+
+    /// Get the stored field of the given record, the data isn't decrypted
+    pub fn field(&self, key: &str, name: &str) -> Result<YorField, YorError> {
+        let fields = match self.live_entry(key)?.y_data {
+            YorDataType::Record(fields) => fields,
+            _ => return Err(YorError::Invalid(format!("Key: {} is not a record", key))),
+        };
+        fields.into_iter().find(|f| f.name == name).ok_or_else(|| {
+            YorError::NotFound(format!("Field: {} of the key: {} not found", name, key))
+        })
+    }
+
+    /// Get the value of a field of the given record
+    pub fn get_field(&self, key: &str, name: &str) -> Result<Vec<u8>, YorError> {
+        let y_type = self.entry(key)?.y_type;
+        self.open_field(key, &y_type, &self.field(key, name)?)
+    }
+
+    /// Get the fields of the given record with their value, the sensitive
+    /// ones are `None` unless `reveal` is set
+    pub fn fields(&self, key: &str, reveal: bool) -> Result<RecordFields, YorError> {
+        let yor = self.live_entry(key)?;
+        let fields = match yor.y_data {
+            YorDataType::Record(fields) => fields,
+            _ => return Err(YorError::Invalid(format!("Key: {} is not a record", key))),
+        };
+        fields
+            .into_iter()
+            .map(|field| {
+                let value = if field.sensitive && !reveal {
+                    None
+                } else {
+                    Some(self.open_field(key, &yor.y_type, &field)?)
+                };
+                Ok((field, value))
+            })
+            .collect()
+    }
+
+    fn open_field(&self, key: &str, y_type: &str, field: &YorField) -> Result<Vec<u8>, YorError> {
+        match &field.data {
+            YorDataType::Bytes(d) => self.decrypt(key, d, &self.ad(key, y_type).field(&field.name)),
+            YorDataType::Str(d) => Ok(d.clone().into_bytes()),
+            YorDataType::Binary(d) => Ok(d.clone()),
+            _ => Err(YorError::Corrupt(format!(
+                "The field: {} is corrupted",
+                field.name
+            ))),
+        }
+    }
+
+    /// Set the given key to a one-time password secret (`otp/totp` or
+    /// `otp/hotp`), it is always encrypted so the vault must be unlocked.
+    pub fn set_otp(
+        &mut self,
+        key: &str,
+        otp: &Otp,
+        details: &EntryDetails,
+    ) -> Result<(), YorError> {
+        otp.validate()?;
+        let y_type = if otp.is_hotp() {
+            "otp/hotp"
+        } else {
+            "otp/totp"
+        };
+        check_type(key, y_type)?;
+        let db_key = self
+            .encryption_key()
+            .ok_or_else(|| YorError::Invalid(String::from("An otp secret needs a password.")))?;
+        let ad = self.ad(key, y_type);
+        let data = YorDataType::Bytes(db_key.encrypt(serde_json::to_vec(otp)?, &ad)?);
+        self.insert(key, YorData::new(data, y_type.to_string()), details)
+    }
+
+    /// Get the one-time password secret of the given key
+    pub fn otp(&self, key: &str) -> Result<Otp, YorError> {
+        let yor = self.entry(key)?;
+        if split_type(&yor.y_type)[0] != "otp" {
+            return Err(YorError::Invalid(format!(
+                "Key: {} is not an otp secret",
+                key
+            )));
+        }
+        Ok(serde_json::from_slice(&self.get(key)?)?)
+    }
+
+    /// Get the current code of the one-time password of the given key, with
+    /// the number of seconds it is valid for (`None` for a HOTP). The counter
+    /// of a HOTP is moved forward and saved, the history isn't changed.
+    pub fn otp_code(&mut self, key: &str) -> Result<(String, Option<u64>), YorError> {
+        let mut otp = self.otp(key)?;
+        let counter = match otp.counter {
+            Some(counter) => counter,
+            None => {
+                let (code, remaining) = otp.totp(now());
+                return Ok((code, Some(remaining)));
+            }
+        };
+        let next = counter.checked_add(1).ok_or_else(|| {
+            YorError::Invalid(format!("The otp counter of the key: {} is too large", key))
+        })?;
+        let code = otp.code(counter);
+        otp.counter = Some(next);
+        let mut yor = self.entry(key)?;
+        let ad = self.ad(key, &yor.y_type);
+        yor.y_data = YorDataType::Bytes(self.key()?.encrypt(serde_json::to_vec(&otp)?, &ad)?);
+        self.db.set(key, &yor)?;
+        self.db.save()?;
+        Ok((code, None))
+    }
+
+    /// Set the entry of the key with the given details, see `replace`
+    fn insert(&mut self, key: &str, yor: YorData, details: &EntryDetails) -> Result<(), YorError> {
+        let previous = self.db.get::<YorData>(key);
+        self.replace(key, yor, previous, details)
+    }
+
+    /// Set the entry of the key and save the database once. The previous
+    /// value is moved to the history, its creation time, note and tags are
+    /// kept unless the details change them. The values beyond the history
+    /// depth are dropped.
+    fn replace(
+        &mut self,
+        key: &str,
+        mut yor: YorData,
+        previous: Option<YorData>,
+        details: &EntryDetails,
+    ) -> Result<(), YorError> {
+        let mut dropped = Vec::new();
+        if let Some(previous) = previous {
+            yor.created_at = previous.created_at;
+            yor.last_accessed = previous.last_accessed;
+            yor.note = previous.note;
+            yor.tags = previous.tags;
+            yor.history = previous.history;
+            yor.history.insert(
+                0,
+                YorHistory {
+                    y_data: previous.y_data,
+                    y_type: previous.y_type,
+                    updated_at: previous.updated_at,
+                    replaced_at: now(),
+                },
+            );
+            let depth = self.history_depth().min(yor.history.len());
+            dropped = yor.history.split_off(depth);
+        }
+        yor.annotate(details.note.as_deref(), &details.tags, &[]);
+        yor.expires_at = details.expires_at;
+        self.db.set(key, &yor)?;
+        self.db.save()?;
+        for earlier in dropped {
+            remove_unique_blob(&earlier.y_data);
+        }
+        Ok(())
+    }
+
+    /// The number of earlier values kept for each key
+    pub fn history_depth(&self) -> usize {
+        get_meta(&self.db).history.unwrap_or(DEFAULT_HISTORY)
+    }
+
+    /// Change the number of earlier values kept for each key and save the
+    /// database, the values beyond it are dropped now.
+    pub fn set_history_depth(&mut self, depth: usize) -> Result<(), YorError> {
+        let mut meta = get_meta(&self.db);
+        meta.history = Some(depth);
+        self.db.set(META_KEY, &meta)?;
+
+        let mut dropped = Vec::new();
+        for (key, mut yor) in self.iter().collect::<Vec<_>>() {
+            if yor.history.len() > depth {
+                dropped.extend(yor.history.split_off(depth));
+                self.db.set(&key, &yor)?;
+            }
+        }
+        self.db.save()?;
+        for earlier in dropped {
+            remove_unique_blob(&earlier.y_data);
+        }
+        Ok(())
+    }
+
+    /// The earlier values of the given key, the most recent first
+    pub fn history(&self, key: &str) -> Result<Vec<YorHistory>, YorError> {
+        Ok(self.entry(key)?.history)
+    }
+
+    /// Get the earlier value of the given key, `n` starts at 1 for the most
+    /// recent one
+    fn earlier(&self, key: &str, n: usize) -> Result<YorHistory, YorError> {
+        n.checked_sub(1)
+            .and_then(|i| {
+                self.entry(key)
+                    .map(|yor| yor.history.get(i).cloned())
+                    .transpose()
+            })
+            .transpose()?
+            .ok_or_else(|| {
+                YorError::NotFound(format!(
+                    "Key: {} has no earlier value #{}, see `yor history {}`",
+                    key, n, key
+                ))
+            })
+    }
+
+    /// Prompt for the password needed to restore the earlier value `n` of
+    /// the given key (see `rollback`), files don't need one.
+    pub fn prompt_unlock_earlier(&mut self, key: &str, n: usize) -> Result<(), YorError> {
+        let earlier = self.earlier(key, n)?;
+        if !matches!(earlier.y_data, YorDataType::Bytes(_)) || self.prompt_unlock()? {
+            return Ok(());
+        }
+        let ad = self.ad(key, &earlier.y_type);
+        let db_key = with_password("[yor] password for the value: ", |password| {
+            DbKey::for_db(&self.db, password)
+                .ok()
+                .filter(|db_key| can_open(&earlier.y_data, db_key, &ad))
+        })?;
+        self.set_key(db_key)
+    }
+
+    /// Restore the earlier value `n` of the given key (1 is the most recent)
+    /// and save the database. The current value takes its place in the
+    /// history so a rollback can be undone. Encrypted values are re-encrypted
+    /// with the key of the vault.
+    pub fn rollback(&mut self, key: &str, n: usize) -> Result<(), YorError> {
+        self.earlier(key, n)?;
+        let mut previous = self.entry(key)?;
+        let earlier = previous.history.remove(n - 1);
+        let data = match earlier.y_data {
+            YorDataType::Bytes(d) => {
+                let ad = self.ad(key, &earlier.y_type);
+                let plain = self.decrypt(key, &d, &ad)?;
+                YorDataType::Bytes(self.key()?.encrypt(plain, &ad)?)
+            }
+            // Blobs are moved back as they are
+            data => data,
+        };
+        let mut yor = YorData::new(data, earlier.y_type);
+        yor.updated_at = earlier.updated_at.or(yor.updated_at);
+        self.replace(key, yor, Some(previous), &EntryDetails::default())
+    }
+
+    /// Change the note and the tags of the given key and save the database,
+    /// the value is left as is.
+    ///
+    /// # Arguments
+    /// - `key` - The key to change
+    /// - `note` - The new note, an empty one removes it
+    /// - `add` - The tags to add
+    /// - `remove` - The tags to remove
+    pub fn annotate(
+        &mut self,
+        key: &str,
+        note: Option<&str>,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), YorError> {
+        let mut yor = self.entry(key)?;
+        yor.annotate(note, add, remove);
+        self.db.set(key, &yor)?;
+        self.db.save()
+    }
+
+    /// Set when the value of the given key expires (unix seconds, `None`
+    /// never) and save the database
+    pub fn set_expiry(&mut self, key: &str, expires_at: Option<i64>) -> Result<(), YorError> {
+        let mut yor = self.entry(key)?;
+        yor.expires_at = expires_at;
+        self.db.set(key, &yor)?;
+        self.db.save()
+    }
+
+    /// Remove the expired keys (and their history) for good and save the
+    /// database. Returns the removed keys.
+    pub fn purge_expired(&mut self) -> Result<Vec<String>, YorError> {
+        let expired: Vec<(String, YorData)> =
+            self.iter().filter(|(_, yor)| yor.is_expired()).collect();
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+        for (key, _) in &expired {
+            self.db.rem(key)?;
+        }
+        self.db.save()?;
+        Ok(expired
+            .into_iter()
+            .map(|(key, yor)| {
+                remove_entry_blobs(&yor);
+                key
+            })
+            .collect())
+    }
+
+    /// Record that the given keys were read now and save the database. When
+    /// the vault is unlocked, their values written in an older format (see
+    /// `is_outdated`) are upgraded on the way. Another command may have
+    /// changed the database since it was loaded, so only the read keys are
+    /// changed in its current content, while it is locked.
+    pub fn touch(&mut self, keys: &[&str]) -> Result<(), YorError> {
+        let _lock = lock_db_file(self.db.path())?;
+        let mut db = reload_db(&self.db)?;
+        set_db_id(&mut db)?;
+        self.db = db;
+        // The values aren't upgraded with a stale key (rekeyed since)
+        let db_key = self
+            .key
+            .clone()
+            .filter(|key| !self.has_master_password() || verify_db_key(&self.db, key));
+        if let Some(key) = db_key.clone() {
+            self.set_key(key)?;
+        }
+        let db_key = db_key.filter(|key| !key.is_empty());
+
+        let now = now();
+        for key in keys {
+            // Removed by another command
+            let mut yor = match self.db.get::<YorData>(key) {
+                Some(yor) => yor,
+                None => continue,
+            };
+            yor.last_accessed = Some(now);
+            if let Some(db_key) = &db_key {
+                upgrade(&mut yor.y_data, db_key, &self.ad(key, &yor.y_type));
+                for earlier in yor.history.iter_mut() {
+                    upgrade(&mut earlier.y_data, db_key, &self.ad(key, &earlier.y_type));
+                }
+            }
+            self.db.set(key, &yor)?;
+        }
+        self.db.write()
+    }
+
+    /// Move the given key (and its history) to the trash of the database and
+    /// save the database
+    pub fn remove(&mut self, key: &str) -> Result<(), YorError> {
+        let data = self.entry(key)?;
+        self.db.rem(key)?;
+        let mut trash = get_trash(&self.db);
+        trash.insert(
+            0,
+            TrashedKey {
+                key: key.to_string(),
+                removed_at: now(),
+                data,
+            },
+        );
+        self.save_trash(trash, false).map(|_| ())
+    }
+
+    /// The removed keys of the database, the most recent first
+    pub fn trash(&self) -> Vec<TrashedKey> {
+        get_trash(&self.db)
+    }
+
+    /// Restore the most recently removed key with the given name and save
+    /// the database
+    pub fn restore_removed(&mut self, key: &str) -> Result<(), YorError> {
+        if self.contains(key) {
+            return Err(YorError::Invalid(format!(
+                "Key: {} already exists, remove it first",
+                key
+            )));
+        }
+        let mut trash = get_trash(&self.db);
+        let index = trash
+            .iter()
+            .position(|t| t.key == key)
+            .ok_or_else(|| YorError::NotFound(format!("Key: {} is not in the trash", key)))?;
+        let trashed = trash.remove(index);
+        self.db.set(key, &trashed.data)?;
+        self.save_trash(trash, false).map(|_| ())
+    }
+
+    /// Remove the keys of the trash for good, only the ones older than the
+    /// trash age unless `all` is set (see `trash_days`). Returns the number of
+    /// removed keys.
+    pub fn purge_trash(&mut self, all: bool) -> Result<usize, YorError> {
+        self.save_trash(get_trash(&self.db), all)
+    }
+
+    /// Save the trash and the database without the purged keys, their files
+    /// are removed afterwards
+    fn save_trash(&mut self, trash: Vec<TrashedKey>, all: bool) -> Result<usize, YorError> {
+        let (purged, trash): (Vec<_>, Vec<_>) = trash
+            .into_iter()
+            .partition(|t| all || is_trash_expired(t.removed_at));
+        if trash.is_empty() {
+            self.db.rem(TRASH_KEY)?;
+        } else {
+            self.db.set(TRASH_KEY, &trash)?;
+        }
+        self.db.save()?;
+        for trashed in &purged {
+            remove_entry_blobs(&trashed.data);
+        }
+        Ok(purged.len())
+    }
+
+    /// Re-encrypt the protected values with a new password, see `rekey`.
+    /// The new database key gets a new salt and the current key derivation
+    /// parameters. Returns the number of rotated entries.
+    pub fn rekey(&mut self, new: &str, filters: &[String]) -> Result<usize, YorError> {
+        let new_key = DbKey::new(new, &salt()?, kdf_params());
+        let old = self.key.take().ok_or(YorError::WrongPassword)?;
+        let in_agent = agent::get(self.db.path(), old.salt(), &old.params()).is_some();
+        let result = rekey(&mut self.db, &old, &new_key, filters);
+        self.key = Some(if result.is_ok() { new_key } else { old });
+        let count = result?;
+        // Keep the database unlocked in the agent
+        if in_agent {
+            let key = self.key()?;
+            let _ = agent::add(
+                self.db.path(),
+                key.salt(),
+                &key.params(),
+                &key.derived_key()?,
+            );
+        }
+        Ok(count)
+    }
+}
+
+/// Check that the type is supported and the key isn't reserved, returns the
+/// type split on `/`
+fn check_type<'a>(key: &str, y_type: &'a str) -> Result<Vec<&'a str>, YorError> {
+    let supported_types = ["image", "video", "file", "data", "otp", "record"];
+    let splitted_type = split_type(y_type);
+    if splitted_type.len() != 2 || !supported_types.contains(&splitted_type[0]) {
+        return Err(YorError::Invalid(format!(
+            "Data type is not supported: {}",
+            y_type
+        )));
+    }
+    if is_reserved_key(key) {
+        return Err(YorError::Invalid(format!(
+            "Keys starting with `{}` are reserved.",
+            RESERVED_PREFIX
+        )));
+    }
+    Ok(splitted_type)
+}
+
+/// Check if the given data can be read with the key
+fn can_open(data: &YorDataType, key: &DbKey, ad: &AssociatedData) -> bool {
+    match data {
+        YorDataType::Bytes(d) => key.decrypt(d, ad).is_ok(),
+        YorDataType::Blob(b) => blob::open_blob(b, Some(key), ad).is_ok(),
+        YorDataType::Str(_) | YorDataType::Binary(_) => true,
+        YorDataType::Record(fields) => fields
+            .iter()
+            .all(|f| can_open(&f.data, key, &ad.clone().field(&f.name))),
+    }
+}
+
+/// Re-encrypt the data if it is in an older format, it is left as is if it
+/// doesn't decrypt with the key (set with another password)
+fn upgrade(data: &mut YorDataType, key: &DbKey, ad: &AssociatedData) {
+    match data {
+        YorDataType::Bytes(d) if is_outdated(&d) => {
+            if let Ok(Ok(upgraded)) = key.decrypt(&d, ad).map(|plain| key.encrypt(plain, ad)) {
+                *d = upgraded;
+            }
+        }
+        YorDataType::Record(fields) => {
+            for field in fields.iter_mut() {
+                upgrade(&mut field.data, key, &ad.clone().field(&field.name));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Remove the blobs of the value and the earlier values of the entry, see
+/// `remove_unique_blob`
+fn remove_entry_blobs(yor: &YorData) {
+    remove_unique_blob(&yor.y_data);
+    for earlier in &yor.history {
+        remove_unique_blob(&earlier.y_data);
+    }
+}
+
+/// Remove the blob of the data from the store if nothing else can reference
+/// it. Encrypted blobs have a random salt so their content address is unique,
+/// the unencrypted ones may be shared between keys and are left to
+/// `sweep_blobs`.
+pub(crate) fn remove_unique_blob(data: &YorDataType) {
+    if let YorDataType::Blob(b) = data {
+        if b.encrypted {
+            let _ = blob::remove_blob(b);
+        }
+    }
+}
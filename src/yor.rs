@@ -17,23 +17,91 @@
  *
 */
 
+use crate::agent;
+use crate::blob::{self, YorBlob};
+use crate::error::YorError;
+use crate::otp::Otp;
+use crate::vault::Vault;
 use anyhow::{bail, ensure, Context, Result};
-use base64;
 use colored::Colorize;
-use dirs;
-use getrandom;
 use orion::aead::SecretKey;
 use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
-use rand::Rng;
-use rpassword;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
+use std::io::{IsTerminal, Read};
+use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use zeroize::Zeroizing;
 
-#[allow(dead_code)]
-fn nonce() -> Result<[u8; 24]> {
+/// Parameters of the Argon2i key derivation used to get a key from a password
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// The number of iterations (at least 3)
+    pub iterations: u32,
+    /// The memory to use in KiB (at least 8)
+    pub memory: u32,
+}
+
+impl Default for KdfParams {
+    /// The parameters used before they were configurable
+    fn default() -> Self {
+        KdfParams {
+            iterations: 15,
+            memory: 1024,
+        }
+    }
+}
+
+impl KdfParams {
+    pub const MIN_ITERATIONS: u32 = 3;
+    pub const MIN_MEMORY: u32 = 8;
+    /// Upper bounds so a tampered header can't make the derivation hang,
+    /// `config kdf` accepts nothing above them
+    pub const MAX_ITERATIONS: u32 = 32;
+    pub const MAX_MEMORY: u32 = 256 * 1024;
+
+    /// Check if the parameters are within the supported bounds
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            (Self::MIN_ITERATIONS..=Self::MAX_ITERATIONS).contains(&self.iterations),
+            "The iterations must be between {} and {}",
+            Self::MIN_ITERATIONS,
+            Self::MAX_ITERATIONS
+        );
+        ensure!(
+            (Self::MIN_MEMORY..=Self::MAX_MEMORY).contains(&self.memory),
+            "The memory must be between {} and {} KiB",
+            Self::MIN_MEMORY,
+            Self::MAX_MEMORY
+        );
+        Ok(())
+    }
+}
+
+/// KDF parameters for the new ciphertexts, read once from the config
+static KDF_PARAMS: OnceLock<KdfParams> = OnceLock::new();
+
+/// Get the KDF parameters used to encrypt new data (the `kdf` config entry)
+pub fn kdf_params() -> KdfParams {
+    *KDF_PARAMS.get_or_init(|| {
+        load_db(&vault_root().join("config"))
+            .ok()
+            .and_then(|conf| conf.get::<KdfParams>("kdf"))
+            .unwrap_or_default()
+    })
+}
+
+pub(crate) fn nonce() -> Result<[u8; 24]> {
     let mut result = [0u8; 24];
-    getrandom::getrandom(&mut result).unwrap();
+    getrandom::getrandom(&mut result).with_context(|| "Could not generate a nonce")?;
+    Ok(result)
+}
+
+pub(crate) fn salt() -> Result<[u8; SALT_SIZE]> {
+    let mut result = [0u8; SALT_SIZE];
+    getrandom::getrandom(&mut result).with_context(|| "Could not generate a salt")?;
     Ok(result)
 }
 
@@ -42,35 +110,91 @@ fn nonce() -> Result<[u8; 24]> {
 /// # Arguments
 /// - `password` - The password used to encrypt/decrypt the data
 /// - `salt` - The salt used to strengthen the encryption
-fn get_key_from_password(password: &str, salt: &[u8]) -> Result<SecretKey> {
+/// - `params` - The Argon2i parameters
+pub(crate) fn get_key_from_password(
+    password: &str,
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<SecretKey> {
     use orion::hazardous::stream::chacha20::CHACHA_KEYSIZE;
     use orion::kdf::{derive_key, Password, Salt};
     let password = Password::from_slice(password.as_bytes()).with_context(|| "Password error")?;
     let salt = Salt::from_slice(salt).with_context(|| "Salt is too short")?;
-    let kdf_key = derive_key(&password, &salt, 15, 1024, CHACHA_KEYSIZE as u32)
-        .with_context(|| "Could not derive key from password")?;
+    let kdf_key = derive_key(
+        &password,
+        &salt,
+        params.iterations,
+        params.memory,
+        CHACHA_KEYSIZE as u32,
+    )
+    .with_context(|| "Could not derive key from password")?;
     let key = SecretKey::from_slice(kdf_key.unprotected_as_bytes())
         .with_context(|| "Could not convert key")?;
     Ok(key)
 }
 
-/// Encrypts the plaintext with the given password and returns the ciphertext. The nonce is generated at each call to strengthen the encryption.
+/// Magic bytes of the versioned ciphertexts, followed by the format version
+const CIPHER_MAGIC: &[u8] = b"YOR";
+/// Format of the ciphertexts without associated data: the values written
+/// before `CIPHER_VERSION_BOUND`, the encrypted databases and the blobs
+/// written before `CIPHER_VERSION_DB_ID`
+pub(crate) const CIPHER_VERSION: u8 = 1;
+/// Format of the values bound to their database, key and type: the header
+/// and the `AssociatedData` are authenticated with the ciphertext. The
+/// database is the name of its file.
+const CIPHER_VERSION_BOUND: u8 = 2;
+/// Format of the values bound to the id of their database (see
+/// `YorMeta::db_id`) rather than its name, with a check value of the
+/// database key so a wrong password isn't taken for a tampered value
+pub(crate) const CIPHER_VERSION_DB_ID: u8 = 3;
+/// Argon2i + XChaCha20-Poly1305
+const ALG_ARGON2I_XCHACHA20POLY1305: u8 = 1;
+/// Argon2i + chunked XChaCha20-Poly1305 (secretstream), used by the blob store
+pub(crate) const ALG_ARGON2I_XCHACHA20POLY1305_STREAM: u8 = 2;
+/// Argon2i database key + HKDF-SHA512 subkey + XChaCha20-Poly1305 (see `DbKey`)
+const ALG_DBKEY_XCHACHA20POLY1305: u8 = 3;
+/// Argon2i database key + HKDF-SHA512 subkey + chunked XChaCha20-Poly1305,
+/// used by the blob store
+pub(crate) const ALG_DBKEY_XCHACHA20POLY1305_STREAM: u8 = 4;
+pub(crate) const SALT_SIZE: usize = 16;
+/// The HKDF info of the subkeys of the values
+const SUBKEY_INFO: &[u8] = b"yor/value";
+/// The HKDF info of the check values of the database key
+const KEY_CHECK_INFO: &[u8] = b"yor/check";
+pub(crate) const KEY_CHECK_SIZE: usize = 16;
+/// magic (3) + version (1) + algorithm (1) + iterations (4) + memory (4) + salt (16)
+pub(crate) const CIPHER_HEADER_SIZE: usize = 3 + 1 + 1 + 4 + 4 + SALT_SIZE;
+
+/// Encrypts the plaintext with the given password and returns the ciphertext.
+/// The KDF parameters come from the `kdf` config entry (see `kdf_params`).
+pub fn encrypt(plaintext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result<Vec<u8>> {
+    encrypt_with(plaintext, password, &kdf_params())
+}
+
+/// Encrypts the plaintext with the given password and KDF parameters and returns the ciphertext.
+/// The salt and the nonce are generated at each call to strengthen the encryption.
 /// Otherwise there's a chance the key is weakened if the same nonce is used.
 /// The nonce is 24 byte (following the XCHACHA_NONCESIZE property).
-/// The ciphertext will be 40 bytes longer than the plaintext because of the XCHACHA_NONCESIZE + POLY1305_OUTSIZE size.
+/// The algorithm, KDF parameters and salt are stored in a header so they can
+/// be changed without breaking the existing ciphertexts.
 ///
 /// ## Format
 ///
-/// {0,24: nonce} {24,: ciphertext} ...
+/// {0,3: "YOR"} {3: version} {4: algorithm} {5,9: iterations (le)} {9,13: memory (le)}
+/// {13,29: salt} {29,53: nonce} {53,: ciphertext} ...
 ///
 /// ## Arguments
 /// - `plaintext`: The plaintext to encrypt
 /// - `password`: The password to use for the encryption
-/// - `salt`: The salt to use for the encryption
+/// - `params`: The KDF parameters to derive the key with
 ///
 /// ## Returns
 /// The ciphertext
-pub fn encrypt(plaintext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result<Vec<u8>> {
+pub fn encrypt_with(
+    plaintext: impl AsRef<[u8]>,
+    password: impl AsRef<str>,
+    params: &KdfParams,
+) -> Result<Vec<u8>> {
     use orion::hazardous::{
         aead::xchacha20poly1305::{seal, Nonce, SecretKey as XSecretKey},
         mac::poly1305::POLY1305_OUTSIZE,
@@ -79,10 +203,11 @@ pub fn encrypt(plaintext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result
     // Fetch param as refs
     let plaintext = plaintext.as_ref();
     let password = password.as_ref();
-    let mut nonce = [0u8; 24];
-    getrandom::getrandom(&mut nonce).unwrap();
+    params.validate()?;
+    let salt = salt()?;
+    let nonce = nonce()?;
     // Get high-level API key
-    let key = get_key_from_password(password, &nonce)?;
+    let key = get_key_from_password(password, &salt, params)?;
     // Convert high-level API key to low-level API key
     let key =
         XSecretKey::from_slice(key.unprotected_as_bytes()).with_context(|| "Key is invalid")?;
@@ -91,32 +216,87 @@ pub fn encrypt(plaintext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result
     let nonce = Nonce::from_slice(&nonce).with_context(|| "Nonce is too short")?;
 
     // Get the output length
-    let output_len = match plaintext
-        .len()
-        .checked_add(XCHACHA_NONCESIZE + POLY1305_OUTSIZE)
-    {
+    let body_start = CIPHER_HEADER_SIZE + XCHACHA_NONCESIZE;
+    let output_len = match plaintext.len().checked_add(body_start + POLY1305_OUTSIZE) {
         Some(min_output_len) => min_output_len,
         None => bail!("Plaintext is too long"),
     };
 
-    // Allocate a buffer for the output
-    let mut output = vec![0u8; output_len];
-    output[..XCHACHA_NONCESIZE].copy_from_slice(nonce.as_ref());
+    // Allocate a buffer for the output and write the header
+    let mut output = Vec::with_capacity(output_len);
+    write_cipher_header(
+        &mut output,
+        CIPHER_VERSION,
+        ALG_ARGON2I_XCHACHA20POLY1305,
+        params,
+        &salt,
+    );
+    output.extend_from_slice(nonce.as_ref());
+    output.resize(output_len, 0);
 
     // Encrypt the plaintext and add it to the end of output buffer
-    seal(
-        &key,
-        &nonce,
-        plaintext,
-        None,
-        &mut output[XCHACHA_NONCESIZE..],
-    )
-    .with_context(|| "Could not convert key")?;
+    seal(&key, &nonce, plaintext, None, &mut output[body_start..])
+        .with_context(|| "Could not convert key")?;
 
     Ok(output)
 }
 
+/// Write the header of a versioned ciphertext (see `encrypt_with`)
+pub(crate) fn write_cipher_header(
+    output: &mut Vec<u8>,
+    version: u8,
+    algorithm: u8,
+    params: &KdfParams,
+    salt: &[u8],
+) {
+    output.extend_from_slice(CIPHER_MAGIC);
+    output.push(version);
+    output.push(algorithm);
+    output.extend_from_slice(&params.iterations.to_le_bytes());
+    output.extend_from_slice(&params.memory.to_le_bytes());
+    output.extend_from_slice(salt);
+}
+
+/// The parsed header of a versioned ciphertext
+pub(crate) struct CipherHeader<'a> {
+    pub(crate) version: u8,
+    pub(crate) algorithm: u8,
+    pub(crate) params: KdfParams,
+    pub(crate) salt: &'a [u8],
+    /// The nonce followed by the encrypted data
+    pub(crate) body: &'a [u8],
+}
+
+/// Read the KDF parameters and salt from the header of a versioned ciphertext.
+/// Returns `None` for the legacy ciphertexts (without header).
+pub(crate) fn parse_cipher_header(ciphertext: &[u8]) -> Result<Option<CipherHeader<'_>>> {
+    if ciphertext.len() < CIPHER_HEADER_SIZE || !ciphertext.starts_with(CIPHER_MAGIC) {
+        return Ok(None);
+    }
+    let version = ciphertext[3];
+    ensure!(
+        (CIPHER_VERSION..=CIPHER_VERSION_DB_ID).contains(&version),
+        "Unsupported ciphertext version: {}",
+        version
+    );
+    let params = KdfParams {
+        iterations: u32::from_le_bytes(ciphertext[5..9].try_into()?),
+        memory: u32::from_le_bytes(ciphertext[9..13].try_into()?),
+    };
+    params.validate()?;
+    Ok(Some(CipherHeader {
+        version,
+        algorithm: ciphertext[4],
+        params,
+        salt: &ciphertext[13..CIPHER_HEADER_SIZE],
+        body: &ciphertext[CIPHER_HEADER_SIZE..],
+    }))
+}
+
 /// Decrypts the ciphertext with the given password and returns the plaintext.
+/// The KDF parameters are read from the ciphertext header, ciphertexts written
+/// before the header existed use the default parameters with the nonce as salt.
+/// Each call runs Argon2i, see `DbKey` to decrypt many values.
 ///
 /// ## Arguments
 /// - `ciphertext`: The ciphertext to decrypt
@@ -124,336 +304,2475 @@ pub fn encrypt(plaintext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result
 ///
 /// ## Returns
 /// The plaintext as bytes
-pub fn decrypt(ciphertext: impl AsRef<[u8]>, password: impl AsRef<str>) -> Result<Vec<u8>> {
-    use orion::aead::open;
-    use orion::hazardous::stream::xchacha20::XCHACHA_NONCESIZE;
+pub fn decrypt(
+    ciphertext: impl AsRef<[u8]>,
+    password: impl AsRef<str>,
+) -> Result<Vec<u8>, YorError> {
+    // The salt is only needed to encrypt
+    DbKey::new(password.as_ref(), &[], KdfParams::default()).open(ciphertext.as_ref(), None)
+}
 
-    let ciphertext = ciphertext.as_ref();
-    let password = password.as_ref();
+/// Check if the ciphertext of a value is in an older format, not bound to
+/// the id of its database, key and type (see `AssociatedData`). Such values
+/// still open, they are upgraded when they are read (see `Vault::touch`) or
+/// rotated.
+pub fn is_outdated(ciphertext: impl AsRef<[u8]>) -> bool {
+    !matches!(
+        parse_cipher_header(ciphertext.as_ref()),
+        Ok(Some(CipherHeader {
+            version: CIPHER_VERSION_DB_ID,
+            ..
+        }))
+    )
+}
 
-    ensure!(
-        ciphertext.len() > XCHACHA_NONCESIZE,
-        "Ciphertext is too short"
-    );
+/// What an encrypted value belongs to, authenticated as the associated data
+/// of its ciphertext. A ciphertext copied to another key, field, type or
+/// database doesn't decrypt. The database is its id, so a copied or renamed
+/// database still opens, the ciphertexts of `CIPHER_VERSION_BOUND` are bound
+/// to its name.
+#[derive(Clone)]
+pub struct AssociatedData {
+    db_id: Vec<u8>,
+    db_name: String,
+    key: String,
+    field: Option<String>,
+    y_type: String,
+}
+
+impl AssociatedData {
+    /// # Arguments
+    /// - `db` - The database of the value, see `set_db_id`
+    /// - `key` - The key of the value
+    /// - `y_type` - The type of the key
+    pub fn new(db: &YorDb, key: &str, y_type: &str) -> AssociatedData {
+        AssociatedData {
+            db_id: get_meta(db).db_id.unwrap_or_default(),
+            db_name: db.name(),
+            key: key.to_string(),
+            field: None,
+            y_type: y_type.to_string(),
+        }
+    }
+
+    /// The associated data of the given field of the record
+    pub fn field(mut self, name: &str) -> AssociatedData {
+        self.field = Some(name.to_string());
+        self
+    }
+
+    /// The bytes authenticated with the ciphertext: the header of the
+    /// ciphertext then each part prefixed with its length, so no two
+    /// different values give the same bytes
+    pub(crate) fn to_bytes(&self, header: &[u8]) -> Vec<u8> {
+        let field = self.field.as_deref().map_or(&[][..], |f| f.as_bytes());
+        let db = match header.get(3) {
+            Some(&CIPHER_VERSION_BOUND) => self.db_name.as_bytes(),
+            _ => &self.db_id,
+        };
+        let mut output = header.to_vec();
+        output.push(self.field.is_some() as u8);
+        for part in [db, self.key.as_bytes(), field, self.y_type.as_bytes()] {
+            output.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            output.extend_from_slice(part);
+        }
+        output
+    }
+}
+
+/// The database keys derived from a password: salt, KDF parameters and key
+type DerivedKeys = Vec<(Vec<u8>, KdfParams, Zeroizing<Vec<u8>>)>;
+
+/// The key of a database, derived once (Argon2i) from the password with the
+/// salt of the database. Each value is encrypted with its own subkey
+/// (HKDF-SHA512 of the database key over a random salt) so reading or
+/// writing many values costs a single Argon2i run. The ciphertexts written
+/// before are still decrypted with the password. The clones share the
+/// derived keys.
+#[derive(Clone)]
+pub struct DbKey {
+    /// `None` for a key handed by the agent (see `from_derived`)
+    password: Option<Zeroizing<String>>,
+    /// The salt of the database key of the new ciphertexts
+    salt: Vec<u8>,
+    params: KdfParams,
+    /// The database keys derived so far, by salt and KDF parameters
+    derived: Arc<Mutex<DerivedKeys>>,
+}
+
+impl DbKey {
+    /// # Arguments
+    /// - `password` - The password of the database
+    /// - `salt` - The salt of the database key
+    /// - `params` - The Argon2i parameters of the database key
+    pub fn new(password: &str, salt: &[u8], params: KdfParams) -> DbKey {
+        DbKey {
+            password: Some(Zeroizing::new(password.to_string())),
+            salt: salt.to_vec(),
+            params,
+            derived: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// A database key that was already derived (see `derived_key`), without
+    /// its password. The ciphertexts of other salts or encrypted with the
+    /// password directly can't be decrypted with it.
+    pub fn from_derived(salt: &[u8], params: KdfParams, key: &[u8]) -> DbKey {
+        let derived = vec![(salt.to_vec(), params, Zeroizing::new(key.to_vec()))];
+        DbKey {
+            password: None,
+            salt: salt.to_vec(),
+            params,
+            derived: Arc::new(Mutex::new(derived)),
+        }
+    }
 
-    // Get the key from the password and salt
-    let key = get_key_from_password(password, &ciphertext[..XCHACHA_NONCESIZE])?;
-    open(&key, ciphertext).with_context(|| "Invalid key password")
+    /// The key of the given database for the password, with the salt and
+    /// KDF parameters of its metadata. Databases without them get a new salt
+    /// and the current parameters (see `set_key_salt`).
+    pub fn for_db(db: &YorDb, password: &str) -> Result<DbKey, YorError> {
+        let meta = get_meta(db);
+        match (meta.key_salt, meta.key_kdf) {
+            (Some(salt), Some(params)) => Ok(DbKey::new(password, &salt, params)),
+            _ => Ok(DbKey::new(password, &salt()?, kdf_params())),
+        }
+    }
+
+    /// The password the key is derived from, `None` for a key of the agent
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref().map(String::as_str)
+    }
+
+    /// Check if the key is derived from an empty password, nothing is
+    /// encrypted with it
+    pub fn is_empty(&self) -> bool {
+        self.password().is_some_and(str::is_empty)
+    }
+
+    /// The salt of the database key
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// The Argon2i parameters of the database key
+    pub fn params(&self) -> KdfParams {
+        self.params
+    }
+
+    /// The database key of the salt and parameters, derived the first time
+    pub fn derived_key(&self) -> Result<Zeroizing<Vec<u8>>, YorError> {
+        Ok(self.db_key(&self.salt, &self.params)?)
+    }
+
+    /// The database key of the given salt and parameters, it is only derived
+    /// the first time
+    fn db_key(&self, db_salt: &[u8], params: &KdfParams) -> Result<Zeroizing<Vec<u8>>> {
+        let mut derived = self.derived.lock().unwrap();
+        if let Some((_, _, key)) = derived.iter().find(|(s, p, _)| s == db_salt && p == params) {
+            return Ok(key.clone());
+        }
+        // A key of the agent only has the key of its own salt
+        let password = self.password().ok_or(YorError::WrongPassword)?;
+        let key = get_key_from_password(password, db_salt, params)?;
+        let key = Zeroizing::new(key.unprotected_as_bytes().to_vec());
+        derived.push((db_salt.to_vec(), *params, key.clone()));
+        Ok(key)
+    }
+
+    /// Derive the subkey of a value from the database key of the given salt
+    /// and parameters
+    fn subkey(&self, db_salt: &[u8], params: &KdfParams, salt: &[u8]) -> Result<SecretKey> {
+        use orion::hazardous::kdf::hkdf::sha512;
+        let db_key = self.db_key(db_salt, params)?;
+        let mut subkey = Zeroizing::new([0u8; 32]);
+        sha512::derive_key(salt, &db_key, Some(SUBKEY_INFO), &mut subkey[..])
+            .with_context(|| "Could not derive the subkey")?;
+        Ok(SecretKey::from_slice(&subkey[..])?)
+    }
+
+    /// The check value of the database key of the given salt and parameters
+    /// for the salt of a subkey
+    fn key_check(
+        &self,
+        db_salt: &[u8],
+        params: &KdfParams,
+        salt: &[u8],
+    ) -> Result<[u8; KEY_CHECK_SIZE]> {
+        use orion::hazardous::kdf::hkdf::sha512;
+        let db_key = self.db_key(db_salt, params)?;
+        let mut check = [0u8; KEY_CHECK_SIZE];
+        sha512::derive_key(salt, &db_key, Some(KEY_CHECK_INFO), &mut check)
+            .with_context(|| "Could not derive the key check")?;
+        Ok(check)
+    }
+
+    /// Write the header of a new ciphertext and derive its subkey from a
+    /// random salt, see `encrypt`. The check value of the database key
+    /// follows the salt from `CIPHER_VERSION_DB_ID`.
+    pub(crate) fn new_subkey(&self, version: u8, algorithm: u8) -> Result<(Vec<u8>, SecretKey)> {
+        self.params.validate()?;
+        let salt = salt()?;
+        let subkey = self.subkey(&self.salt, &self.params, &salt)?;
+        let mut header = Vec::with_capacity(CIPHER_HEADER_SIZE + SALT_SIZE + KEY_CHECK_SIZE);
+        write_cipher_header(&mut header, version, algorithm, &self.params, &self.salt);
+        header.extend_from_slice(&salt);
+        if version >= CIPHER_VERSION_DB_ID {
+            header.extend_from_slice(&self.key_check(&self.salt, &self.params, &salt)?);
+        }
+        Ok((header, subkey))
+    }
+
+    /// Derive the subkey of the ciphertext with the given header, returns it
+    /// with the rest of the body (the nonce and the encrypted data). A check
+    /// value that doesn't match is a wrong password.
+    pub(crate) fn open_subkey<'a>(
+        &self,
+        header: &CipherHeader<'a>,
+    ) -> Result<(SecretKey, &'a [u8])> {
+        let check_size = match header.version >= CIPHER_VERSION_DB_ID {
+            true => KEY_CHECK_SIZE,
+            false => 0,
+        };
+        ensure!(
+            header.body.len() >= SALT_SIZE + check_size,
+            YorError::Corrupt(String::from("Ciphertext is too short"))
+        );
+        let (salt, body) = header.body.split_at(SALT_SIZE);
+        let (check, body) = body.split_at(check_size);
+        if check_size > 0 {
+            let expected = self.key_check(header.salt, &header.params, salt)?;
+            ensure!(
+                orion::util::secure_cmp(check, &expected).is_ok(),
+                YorError::WrongPassword
+            );
+        }
+        Ok((self.subkey(header.salt, &header.params, salt)?, body))
+    }
+
+    /// Encrypts the plaintext with a new subkey of the database key and
+    /// returns the ciphertext, bound to the given associated data.
+    ///
+    /// ## Format
+    ///
+    /// {0,29: header (see `encrypt_with`), the version is
+    /// `CIPHER_VERSION_DB_ID` and the salt is the one of the database key}
+    /// {29,45: salt of the subkey} {45,61: check value of the database key}
+    /// {61,85: nonce} {85,: ciphertext}
+    pub fn encrypt(&self, plaintext: impl AsRef<[u8]>, ad: &AssociatedData) -> Result<Vec<u8>> {
+        use orion::hazardous::{
+            aead::xchacha20poly1305::{seal, Nonce, SecretKey as XSecretKey},
+            mac::poly1305::POLY1305_OUTSIZE,
+        };
+        let plaintext = plaintext.as_ref();
+        let (mut output, subkey) =
+            self.new_subkey(CIPHER_VERSION_DB_ID, ALG_DBKEY_XCHACHA20POLY1305)?;
+        let subkey = XSecretKey::from_slice(subkey.unprotected_as_bytes())
+            .with_context(|| "Key is invalid")?;
+        let ad = ad.to_bytes(&output);
+        let nonce = nonce()?;
+        output.extend_from_slice(&nonce);
+        let body_start = output.len();
+        output.resize(body_start + plaintext.len() + POLY1305_OUTSIZE, 0);
+        seal(
+            &subkey,
+            &Nonce::from(nonce),
+            plaintext,
+            Some(&ad),
+            &mut output[body_start..],
+        )
+        .with_context(|| "Could not encrypt the value")?;
+        Ok(output)
+    }
+
+    /// Decrypts a ciphertext of `encrypt` with the associated data it was
+    /// encrypted with, and returns the plaintext. The ciphertexts of the
+    /// older formats (see `is_outdated`) aren't bound to anything.
+    pub fn decrypt(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        ad: &AssociatedData,
+    ) -> Result<Vec<u8>, YorError> {
+        self.open(ciphertext.as_ref(), Some(ad))
+    }
+
+    /// Decrypts a ciphertext of `encrypt`, or of `encrypt_with` with the
+    /// password. The bound ciphertexts need their associated data.
+    fn open(&self, ciphertext: &[u8], ad: Option<&AssociatedData>) -> Result<Vec<u8>, YorError> {
+        use orion::aead::open;
+        use orion::hazardous::{
+            aead::xchacha20poly1305::{open as open_with_ad, Nonce, SecretKey as XSecretKey},
+            mac::poly1305::POLY1305_OUTSIZE,
+            stream::xchacha20::XCHACHA_NONCESIZE,
+        };
+
+        let corrupt = |e: anyhow::Error| YorError::Corrupt(e.to_string());
+        let header = match parse_cipher_header(ciphertext).map_err(corrupt)? {
+            Some(header) => header,
+            // Legacy format: {0,24: nonce} {24,: ciphertext}
+            None => CipherHeader {
+                version: CIPHER_VERSION,
+                algorithm: ALG_ARGON2I_XCHACHA20POLY1305,
+                params: KdfParams::default(),
+                salt: ciphertext.get(..XCHACHA_NONCESIZE).unwrap_or_default(),
+                body: ciphertext,
+            },
+        };
+
+        let (key, body) = match header.algorithm {
+            // Get the key from the password and salt
+            ALG_ARGON2I_XCHACHA20POLY1305 => (
+                get_key_from_password(
+                    self.password().ok_or(YorError::WrongPassword)?,
+                    header.salt,
+                    &header.params,
+                )?,
+                header.body,
+            ),
+            ALG_DBKEY_XCHACHA20POLY1305 => self.open_subkey(&header)?,
+            algorithm => {
+                return Err(YorError::Corrupt(format!(
+                    "Unsupported encryption algorithm: {}",
+                    algorithm
+                )))
+            }
+        };
+        if body.len() <= XCHACHA_NONCESIZE {
+            return Err(YorError::Corrupt(String::from("Ciphertext is too short")));
+        }
+        if header.version == CIPHER_VERSION {
+            // The tag can't tell a wrong password from a tampered ciphertext
+            return open(&key, body).map_err(|_| YorError::WrongPassword);
+        }
+
+        let ad = match (header.algorithm, ad) {
+            (ALG_DBKEY_XCHACHA20POLY1305, Some(ad)) => ad,
+            _ => {
+                return Err(YorError::Corrupt(String::from(
+                    "The value can't be decrypted without its key and type",
+                )))
+            }
+        };
+        if body.len() < XCHACHA_NONCESIZE + POLY1305_OUTSIZE {
+            return Err(YorError::Corrupt(String::from("Ciphertext is too short")));
+        }
+        let (nonce, sealed) = body.split_at(XCHACHA_NONCESIZE);
+        let ad = ad.to_bytes(&ciphertext[..ciphertext.len() - body.len()]);
+        let key = XSecretKey::from_slice(key.unprotected_as_bytes())
+            .map_err(|_| YorError::Corrupt(String::from("Key is invalid")))?;
+        let nonce = Nonce::from_slice(nonce)
+            .map_err(|_| YorError::Corrupt(String::from("Nonce is too short")))?;
+        let mut plaintext = vec![0u8; sealed.len() - POLY1305_OUTSIZE];
+        // The key is checked first from `CIPHER_VERSION_DB_ID`, before that a
+        // wrong password looks like a ciphertext tampered with or moved from
+        // another key
+        open_with_ad(&key, &nonce, sealed, Some(&ad), &mut plaintext).map_err(|_| match header
+            .version
+        {
+            CIPHER_VERSION_BOUND => YorError::WrongPassword,
+            _ => YorError::Corrupt(String::from(
+                "The value was tampered with or moved from another key",
+            )),
+        })?;
+        Ok(plaintext)
+    }
 }
 
 /// Data enum for handling data types
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum YorDataType {
     Bytes(Vec<u8>),
     Str(String),
+    /// A file stored in the blob store
+    Blob(YorBlob),
+    /// An unencrypted value that isn't valid UTF-8
+    Binary(Vec<u8>),
+    /// A record (login, card or note) of named fields
+    Record(Vec<YorField>),
+}
+
+/// A field of a record, sensitive fields are encrypted when a password is
+/// set and masked when the record is shown
+#[derive(Serialize, Deserialize, Clone)]
+pub struct YorField {
+    pub name: String,
+    /// `Bytes` when encrypted, `Str` or `Binary` otherwise
+    pub data: YorDataType,
+    pub sensitive: bool,
+}
+
+/// The fields of a record with their values, `None` for the masked ones
+pub type RecordFields = Vec<(YorField, Option<Vec<u8>>)>;
+
+/// The kinds of records (`record/<kind>`) and their sensitive fields, the
+/// other fields are stored as plain text unless set as secrets
+pub const RECORD_KINDS: [(&str, &[&str]); 3] = [
+    ("login", &["password", "totp"]),
+    ("card", &["number", "cvv", "pin"]),
+    ("note", &["text"]),
+];
+
+/// Check if the field of the given kind of record is sensitive by default
+pub fn is_sensitive_field(kind: &str, field: &str) -> bool {
+    RECORD_KINDS
+        .iter()
+        .any(|(k, fields)| *k == kind && fields.contains(&field))
 }
 #[derive(Serialize, Deserialize)]
 pub struct YorData {
     pub y_data: YorDataType,
     pub y_type: String,
+    /// When the key was first set (unix seconds), unknown for older keys
+    #[serde(default)]
+    pub created_at: Option<i64>,
+    /// When the value was last set (unix seconds)
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    /// When the value was last read (unix seconds)
+    #[serde(default)]
+    pub last_accessed: Option<i64>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The earlier values of the key, the most recent first
+    #[serde(default)]
+    pub history: Vec<YorHistory>,
+    /// When the value expires (unix seconds), it can't be read afterwards
+    #[serde(default)]
+    pub expires_at: Option<i64>,
 }
 
-pub fn create_db(path: &str) -> PickleDb {
-    PickleDb::new(
-        path,
-        PickleDbDumpPolicy::AutoDump,
-        SerializationMethod::Json,
-    )
+/// An earlier value of a key, stored as it was (encrypted or not)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct YorHistory {
+    pub y_data: YorDataType,
+    pub y_type: String,
+    /// When the value was set (unix seconds), unknown for older keys
+    pub updated_at: Option<i64>,
+    /// When the value was replaced (unix seconds)
+    pub replaced_at: i64,
 }
 
-pub fn get_password(prompt: &str) -> String {
-    rpassword::prompt_password(prompt).unwrap()
+impl YorDataType {
+    /// Check if a password is needed to read the data
+    pub fn is_encrypted(&self) -> bool {
+        match self {
+            YorDataType::Bytes(_) => true,
+            YorDataType::Blob(b) => b.encrypted,
+            YorDataType::Str(_) | YorDataType::Binary(_) => false,
+            YorDataType::Record(fields) => fields.iter().any(|f| f.data.is_encrypted()),
+        }
+    }
 }
 
-pub fn load_db(path: &Path) -> Result<PickleDb> {
-    PickleDb::load_json(path, PickleDbDumpPolicy::AutoDump)
-        .with_context(|| "Database not found. Consider creating using `create`")
-}
+impl YorData {
+    /// A new entry created now, without note or tags
+    pub fn new(y_data: YorDataType, y_type: String) -> YorData {
+        let now = now();
+        YorData {
+            y_data,
+            y_type,
+            created_at: Some(now),
+            updated_at: Some(now),
+            last_accessed: None,
+            note: None,
+            tags: Vec::new(),
+            history: Vec::new(),
+            expires_at: None,
+        }
+    }
 
-fn init_config_db() {
-    let env = dirs::home_dir().unwrap().as_path().join(".yor");
+    /// Check if the value is expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(0)
+    }
 
-    if !env.join("config").as_path().exists() {
-        let mut db = load_db(env.join("config").as_path())
-            .unwrap_or_else(|_| create_db(env.join("config").to_str().unwrap()));
+    /// Check if the value expires in the given number of seconds
+    pub fn expires_within(&self, seconds: i64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now() + seconds)
+    }
 
-        db.set("db_name", &String::from("default")).unwrap();
-        db.set(
-            "file_env",
-            &String::from(env.join("files").to_str().unwrap()),
-        )
-        .unwrap();
+    /// Change the note (an empty one removes it) and the tags of the entry
+    pub fn annotate(&mut self, note: Option<&str>, add: &[String], remove: &[String]) {
+        if let Some(note) = note {
+            self.note = Some(note.to_string()).filter(|n| !n.is_empty());
+        }
+        self.tags.retain(|tag| !remove.contains(tag));
+        for tag in add {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
     }
-}
 
-pub fn initialize_env() -> Result<()> {
-    let home = dirs::home_dir().unwrap();
-    let env = home.as_path().join(".yor");
-    let db_path = env.as_path().join("db");
-    let default_db = db_path.as_path().join("default");
-    let file_path = env.as_path().join("files");
+    /// Check if the entry has all the given tags
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
 
-    fs::create_dir_all(env).unwrap();
-    fs::create_dir_all(db_path).unwrap();
-    fs::create_dir_all(file_path).unwrap();
-    init_config_db();
+    /// Check if a password is needed to read the data
+    pub fn is_encrypted(&self) -> bool {
+        self.y_data.is_encrypted()
+    }
 
-    // Initialize default db
+    /// Check if the data is a record of fields
+    pub fn is_record(&self) -> bool {
+        matches!(self.y_data, YorDataType::Record(_))
+    }
 
-    load_db(&default_db).unwrap_or_else(|_| create_db(&default_db.to_str().unwrap()));
+    /// Check if the data is a file, image or video (see `FILE_TYPES`)
+    pub fn is_file(&self) -> bool {
+        FILE_TYPES.contains(&split_type(&self.y_type)[0])
+    }
 
-    Ok(())
+    /// The ids of the blobs of the value and of the earlier values
+    pub fn blob_ids(&self) -> impl Iterator<Item = String> + '_ {
+        std::iter::once(&self.y_data)
+            .chain(self.history.iter().map(|earlier| &earlier.y_data))
+            .filter_map(|data| match data {
+                YorDataType::Blob(b) => Some(b.id.clone()),
+                _ => None,
+            })
+    }
 }
 
-/// Get the config data.
-/// # Return (tuple)
-/// - `key` - The password key of the given database
-/// - `db_name` - The name of the database stored
-pub fn get_config_data() -> PickleDb {
-    let home = dirs::home_dir().unwrap();
-    let cfg_path = home.as_path().join(".yor").join("config");
-    load_db(cfg_path.as_path()).unwrap_or_else(|_| {
-        println!(
-            "{}",
-            "Database not found. Consider creating using `create`".truecolor(157, 123, 125)
-        );
-        std::process::exit(1);
-    })
+/// The current time in unix seconds
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
 }
 
-/// Get the db path from the environment given the name
+/// Parse a time-to-live like `90s`, `30m`, `8h`, `7d`, `2w` or `1h30m` into
+/// seconds
+pub fn parse_ttl(ttl: &str) -> Result<i64, YorError> {
+    let invalid = || {
+        YorError::Invalid(format!(
+            "Invalid time-to-live: {} (e.g. 30m, 8h, 7d or 1h30m)",
+            ttl
+        ))
+    };
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    for c in ttl.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value: i64 = number.parse().map_err(|_| invalid())?;
+        seconds = value
+            .checked_mul(unit)
+            .and_then(|v| seconds.checked_add(v))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
+/// Parse a date in RFC 3339 (`2026-10-16T18:00:00Z`) or in the local time
+/// zone (`2026-10-16 18:00` or `2026-10-16` for midnight) into unix seconds
+pub fn parse_date(date: &str) -> Result<i64, YorError> {
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time.timestamp());
+    }
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        });
+    local
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(|time| time.timestamp())
+        .ok_or_else(|| {
+            YorError::Invalid(format!(
+                "Invalid date: {} (e.g. 2026-10-16, 2026-10-16 18:00 or RFC 3339)",
+                date
+            ))
+        })
+}
+
+/// Types stored as files, the value given to `set` is the path of the file
+pub const FILE_TYPES: [&str; 3] = ["video", "file", "image"];
+
+/// Header of the databases that are encrypted at rest, followed by the
+/// encrypted json of the whole database.
+const SEALED_DB_MAGIC: &[u8] = b"YORDB\x00";
+
+/// A database loaded from the environment.
 ///
-/// # Arguments
-/// - `name` - The name of the database
-pub fn get_db_path(name: &str) -> PathBuf {
-    let home = dirs::home_dir().unwrap();
-    let yor_path = home.as_path().join(".yor");
-    let db_path = yor_path.as_path().join("db");
-    db_path.join(&name)
+/// Changes are kept in memory until `save` is called. Encrypted databases are
+/// stored as a single encrypted blob so the key names and types aren't
+/// readable without the password.
+pub struct YorDb {
+    db: PickleDb,
+    path: PathBuf,
+    /// The key of the encryption at rest, the database key of the master
+    /// password
+    seal_key: Option<DbKey>,
 }
 
-/// Print all the database that can be found from the environment
-/// directories
-pub fn print_all_db() {
-    let home = dirs::home_dir().unwrap();
-    let db_path = home.as_path().join(".yor").join("db");
-    let conf = get_config_data();
-    let default_db_name = conf.get::<String>("db_name").unwrap();
+impl Deref for YorDb {
+    type Target = PickleDb;
 
-    if let Ok(entries) = fs::read_dir(db_path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let mut db_name = String::from(entry.file_name().to_str().unwrap());
-                if db_name == default_db_name {
-                    db_name.push_str(&" (current)".truecolor(164, 141, 110).to_string());
+    fn deref(&self) -> &PickleDb {
+        &self.db
+    }
+}
 
-                    // db_name += &" (current)".truecolor(164, 141, 110).to_string();
-                }
-                println!("{}", db_name.truecolor(172, 138, 172));
-            }
-        }
+impl DerefMut for YorDb {
+    fn deref_mut(&mut self) -> &mut PickleDb {
+        &mut self.db
     }
 }
-/// Print all the files that can be found from the environment
-/// directories
-pub fn print_all_files() {
-    let home = dirs::home_dir().unwrap();
-    let db_path = home.as_path().join(".yor").join("files");
 
-    if let Ok(entries) = fs::read_dir(db_path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let filename = String::from(entry.file_name().to_str().unwrap());
+impl YorDb {
+    /// Write the database to its file. The file is replaced atomically so a
+    /// crash can't leave it half written.
+    pub fn save(&mut self) -> Result<(), YorError> {
+        let _lock = lock_db_file(&self.path)?;
+        self.write()
+    }
 
-                println!("{}", filename.truecolor(172, 138, 172));
-            }
+    /// Write the database to its file, the caller holds its lock (see
+    /// `lock_db_file`)
+    pub(crate) fn write(&mut self) -> Result<(), YorError> {
+        // Same layout as the pickledb json files: (map, list_map)
+        let mut map: HashMap<String, String> = HashMap::new();
+        for key in self.db.get_all() {
+            let value = self
+                .db
+                .get::<serde_json::Value>(&key)
+                .ok_or_else(|| YorError::Corrupt(format!("Cannot read the key: {}", key)))?;
+            map.insert(key, value.to_string());
         }
+        let list_map: HashMap<String, Vec<String>> = HashMap::new();
+        let plain = serde_json::to_vec(&(map, list_map))?;
+
+        let key = match &self.seal_key {
+            Some(key) => key,
+            None => return write_atomic(&self.path, &plain),
+        };
+        let mut sealed = SEALED_DB_MAGIC.to_vec();
+        sealed.extend(key.encrypt(plain, &sealed_db_ad())?);
+        write_atomic(&self.path, &sealed)
+    }
+
+    /// The key of the encryption at rest, if the database is encrypted
+    pub(crate) fn seal_key(&self) -> Option<&DbKey> {
+        self.seal_key.as_ref()
+    }
+
+    /// The path of the database file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the database, the name of its file
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
     }
 }
 
-fn encrypt_file(path: &str, key: &str) -> Vec<u8> {
-    let data = fs::read(Path::new(path)).unwrap();
-    encrypt(base64::encode(data), key).unwrap()
+/// Write the data to a temporary file next to the path and rename it over
+/// the path. The file and then its directory are synced, so after a crash the
+/// path has either the old or the new data.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<(), YorError> {
+    use std::io::Write;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path))
+        .and_then(|_| sync_dir(path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.map_err(|e| YorError::Io(format!("Cannot write the database: {}", e)))
 }
-fn write_file(path: &str, data: String) -> Result<()> {
-    let path = Path::new(path);
-    let raw = base64::decode(data).unwrap();
-    fs::write(path, raw).with_context(|| "Cannot write the file")?;
+
+/// Sync the directory of the path, so a file renamed to it is kept after a
+/// crash
+pub(crate) fn sync_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)?.sync_all()?;
+    }
     Ok(())
 }
-#[allow(dead_code)] // for future use
-fn gen_random(len: usize) -> String {
-    rand::thread_rng()
-        .sample_iter::<char, _>(rand::distributions::Standard)
-        .take(len)
-        .collect()
+
+/// An exclusive lock of a database file, released when dropped
+pub(crate) struct DbLock {
+    _file: fs::File,
 }
-#[allow(dead_code)] // for future use
-fn generate_file_session(filename: &str) -> String {
-    let random = gen_random(5);
-    format!("{0}-{1}", filename, random).to_string()
+
+/// Lock the database file, waiting for the other commands writing it. The
+/// lock is taken on a `.<name>.lock` file next to the database since the
+/// database file is replaced on each save.
+pub(crate) fn lock_db_file(path: &Path) -> Result<DbLock, YorError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let lock_path = path.with_file_name(format!(".{}.lock", name));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| YorError::Io(format!("Cannot lock the database: {}", e)))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(YorError::Io(format!(
+                "Cannot lock the database: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(DbLock { _file: file })
 }
-fn split_type(string: &str) -> Vec<&str> {
-    string.split("/").collect()
+
+/// Read the database again from its file, an encrypted database is
+/// decrypted with the key it was decrypted with before
+pub(crate) fn reload_db(db: &YorDb) -> Result<YorDb, YorError> {
+    let content = read_db_file(&db.path)?;
+    match (content.strip_prefix(SEALED_DB_MAGIC), &db.seal_key) {
+        (Some(sealed), Some(key)) => {
+            let plain = key.open(sealed, Some(&sealed_db_ad()))?;
+            open_sealed_db(&db.path, &plain, key.clone())
+        }
+        (Some(_), None) => Err(YorError::WrongPassword),
+        (None, _) => load_plain_db(&db.path),
+    }
+}
+
+/// Keys of the encrypted databases unlocked by this process
+static UNLOCKED_DBS: Mutex<BTreeMap<PathBuf, DbKey>> = Mutex::new(BTreeMap::new());
+
+/// The encrypted json of a database isn't bound to a database or key, the
+/// metadata it contains is
+fn sealed_db_ad() -> AssociatedData {
+    AssociatedData {
+        db_id: Vec::new(),
+        db_name: String::new(),
+        key: String::new(),
+        field: None,
+        y_type: String::from("database"),
+    }
+}
+
+/// Check if the database file at the given path is encrypted at rest
+pub fn is_sealed_db(path: &Path) -> bool {
+    let mut magic = [0u8; SEALED_DB_MAGIC.len()];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map(|_| magic == SEALED_DB_MAGIC)
+        .unwrap_or(false)
 }
 
-/// Update or insert the given item
+/// Create a new empty database, it is written on the first `save`.
 ///
 /// # Arguments
-/// - `db_name` - The name of the database (default)
-/// - `password` - The password used to encrypt/decrypt the data
-/// - `key` - The given key for the value to store
-/// - `value` - The given value for the key to store
-pub fn upsert_item(db_name: String, password: String, key: String, value: String, r#type: String) {
-    let supported_types = ["image", "video", "file", "data"];
-    let file_types = ["video", "file", "image"];
-    if !supported_types.iter().any(|&i| i == split_type(&r#type)[0]) {
-        println!("{}", r#type);
-        println!("Data type is not supported");
-        std::process::exit(1);
-    }
-
-    let mut db: PickleDb = load_db(&get_db_path(&db_name)).unwrap_or_else(|_| {
-        println!(
-            "{}",
-            "Database not found. Consider creating using `create`".truecolor(157, 123, 125)
-        );
-        std::process::exit(1);
-    });
+/// - `path` - The path of the database
+/// - `seal_key` - The key to encrypt the whole database with, if any
+pub fn create_db(path: &str, seal_key: Option<DbKey>) -> YorDb {
+    let policy = match seal_key {
+        // The inner db must never write the plaintext to the disk
+        Some(_) => PickleDbDumpPolicy::NeverDump,
+        None => PickleDbDumpPolicy::DumpUponRequest,
+    };
+    YorDb {
+        db: PickleDb::new(path, policy, SerializationMethod::Json),
+        path: PathBuf::from(path),
+        seal_key,
+    }
+}
+
+/// Where the passwords are read from
+pub enum PasswordSource {
+    /// Prompt on the terminal (default)
+    Prompt,
+    /// One password per line from the standard input
+    Stdin,
+    /// One password per line from the given file
+    File(PathBuf),
+    /// The `YOR_PASSWORD` variable, and `YOR_NEW_PASSWORD` for a new password
+    Env,
+}
+
+static PASSWORD_SOURCE: OnceLock<PasswordSource> = OnceLock::new();
+/// Remaining lines of the password file
+static PASSWORD_LINES: Mutex<Option<VecDeque<String>>> = Mutex::new(None);
+
+/// Set where the passwords are read from. Without `--password-stdin` or
+/// `--password-file`, `YOR_PASSWORD` is used when it is set, otherwise the
+/// passwords are prompted.
+///
+/// # Arguments
+/// - `stdin` - Read the passwords from the standard input
+/// - `file` - Read the passwords from the given file
+pub fn set_password_source(stdin: bool, file: Option<PathBuf>) {
+    let source = if stdin {
+        PasswordSource::Stdin
+    } else if let Some(file) = file {
+        PasswordSource::File(file)
+    } else if std::env::var_os("YOR_PASSWORD").is_some() {
+        PasswordSource::Env
+    } else {
+        PasswordSource::Prompt
+    };
+    let _ = PASSWORD_SOURCE.set(source);
+}
+
+fn password_source() -> &'static PasswordSource {
+    PASSWORD_SOURCE.get_or_init(|| {
+        if std::env::var_os("YOR_PASSWORD").is_some() {
+            PasswordSource::Env
+        } else {
+            PasswordSource::Prompt
+        }
+    })
+}
+
+/// Check if the passwords are prompted, wrong passwords are only retried then
+pub fn is_interactive() -> bool {
+    matches!(password_source(), PasswordSource::Prompt)
+}
 
-    // Set the Data to DataEnum that has 2 types, Vec<u8> and String since
-    // I have no idea how to mutate types in rust.
-    let mut data = YorDataType::Str(value.clone());
-    let mut _type = r#type;
-    if password != "" {
-        data = YorDataType::Bytes(encrypt(value.clone(), password.clone()).unwrap());
-        if split_type(&_type)[1] == "str" {
-            _type = String::from("data/byte");
+/// Read the next password line from the standard input or the password file
+fn next_password_line() -> Result<String, YorError> {
+    let line = match password_source() {
+        PasswordSource::Stdin => {
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(n) if n > 0 => Some(line),
+                _ => None,
+            }
+        }
+        PasswordSource::File(path) => {
+            let mut lines = PASSWORD_LINES.lock().unwrap();
+            if lines.is_none() {
+                let content = fs::read_to_string(path).map_err(|e| {
+                    YorError::Io(format!(
+                        "Cannot read the password file: {} ({})",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                *lines = Some(content.lines().map(String::from).collect());
+            }
+            lines.as_mut().and_then(|lines| lines.pop_front())
         }
+        _ => None,
+    };
+    match line {
+        Some(line) => Ok(line.trim_end_matches(['\r', '\n']).to_string()),
+        None => Err(YorError::Invalid(String::from(
+            "Not enough passwords were given.",
+        ))),
     }
-    /*
-    match data {
-        YorDataType::Bytes(d) => db.set(&key, &d).unwrap(),
-        YorDataType::Str(d) => db.set(&key, &d).unwrap()
+}
+
+/// Get a password from the configured source (see `set_password_source`)
+pub fn get_password(prompt: &str) -> Result<String, YorError> {
+    match password_source() {
+        PasswordSource::Prompt => rpassword::prompt_password(prompt).map_err(|_| {
+            YorError::Invalid(String::from(
+                "Cannot prompt for the password. Use --password-stdin, --password-file or YOR_PASSWORD.",
+            ))
+        }),
+        PasswordSource::Env => Ok(std::env::var("YOR_PASSWORD").unwrap_or_default()),
+        _ => next_password_line(),
     }
-    */
+}
 
-    let mut yordata = YorData {
-        y_data: data,
-        y_type: _type.clone(),
-    };
-    if file_types.iter().any(|&i| i == split_type(&_type)[0]) {
-        if password != "" {
-            yordata.y_data = YorDataType::Bytes(encrypt_file(&value, &password));
+/// Get a new password, prompted with its confirmation (fails if they don't
+/// match). An empty password is returned as is (no encryption).
+pub fn get_new_password(prompt: &str) -> Result<String, YorError> {
+    match password_source() {
+        PasswordSource::Prompt => {}
+        PasswordSource::Env => {
+            return Ok(std::env::var("YOR_NEW_PASSWORD")
+                .or_else(|_| std::env::var("YOR_PASSWORD"))
+                .unwrap_or_default())
         }
-        let d = fs::read(Path::new(&value)).unwrap();
-        yordata.y_data = YorDataType::Str(base64::encode(d));
+        _ => return next_password_line(),
+    }
+    let pwd = get_password(prompt)?;
+    if !pwd.is_empty() && pwd != get_password("[yor] confirm password: ")? {
+        return Err(YorError::Invalid(String::from("Password does not match.")));
     }
-    db.set(&key, &yordata).unwrap();
+    Ok(pwd)
 }
 
-/// Get the value of the given key with the password to decrypt the data
+/// Ask for a password until `check` accepts it, 3 attempts when the password
+/// is prompted and a single one otherwise. Fails with `WrongPassword` when
+/// every attempt failed.
 ///
 /// # Arguments
-/// - `db_name` - The name of the database (default)
-/// - `password` - The password used to encrypt/decrypt the data
-/// - `key` - The given key for the value to get
-#[allow(unused_assignments)]
-pub fn get_item(db_name: String, key: String, out: Option<String>) -> String {
-    let file_types = ["video", "file", "image"];
-    let db: PickleDb = load_db(&get_db_path(&db_name)).unwrap_or_else(|_| {
-        println!("Database not found. Consider creating using `create`");
-        std::process::exit(1);
-    });
-    let exists = db.exists(&key);
-
-    let mut data = String::from("");
-    let mut raw = YorDataType::Str(String::from(""));
-    let mut y_type = String::from("data/str");
-
-    if exists {
-        let yor = db.get::<YorData>(&key).unwrap();
-        raw = yor.y_data;
-        y_type = yor.y_type;
-    }
-    let splitted_type = split_type(&y_type);
-    let configdb = get_config_data();
-    
-    let pathstr = configdb.get::<String>("file_env").unwrap();
-    let mut path = Path::new(&pathstr).join(format!("{}.{}", &key, splitted_type[1]));
-    
-    if splitted_type[1] == "bin" {
-        path = Path::new(&pathstr).join(&key);
+/// - `prompt` - The prompt of the password
+/// - `check` - Returns the unlocked value if the password is valid
+pub fn with_password<T>(
+    prompt: &str,
+    mut check: impl FnMut(&str) -> Option<T>,
+) -> Result<T, YorError> {
+    let attempts = if is_interactive() { 3 } else { 1 };
+    for tries in 1..=attempts {
+        let password = get_password(prompt)?;
+        if let Some(value) = check(&password) {
+            return Ok(value);
+        }
+        if tries < attempts {
+            warn(String::from("Password is invalid. Please try again"));
+        }
     }
+    Err(YorError::WrongPassword)
+}
 
-    if let Some(o) = out {
-        path = Path::new(&o).to_path_buf();
-    } 
-
-    match raw {
-        YorDataType::Bytes(d) => {
-            let mut tries = 1;
-            let mut password = get_password("[yor] password for the key: ");
-
-            let decrypted_data = decrypt(d, password);
-
-            // Get the key three times, if it fails then exit
-            while !decrypted_data.is_ok() {
-                println!(
-                    "{}",
-                    "Password is invalid. Pleae try again".truecolor(157, 123, 125)
-                );
-                password = get_password("[yor] password for the key: ");
-
-                tries += 1;
-                if tries >= 3 {
-                    println!(
-                        "{}",
-                        "Failed after 3 attempts. Are you sure the password is correct?."
-                            .truecolor(157, 123, 125)
-                    );
-                    std::process::exit(1);
-                }
-            }
+/// Ask for a confirmation of a destructive action, `yes` skips it. Without a
+/// terminal to prompt on, `--yes` is required.
+pub fn confirm(prompt: String, yes: bool) -> Result<bool, YorError> {
+    if yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(YorError::Invalid(String::from(
+            "Cannot prompt for a confirmation. Use --yes to confirm.",
+        )));
+    }
+    Ok(dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .interact()
+        .unwrap_or(false))
+}
 
-            println!("{:?}", splitted_type);
-            if file_types.iter().any(|&i| i == splitted_type[0]) {
-                // writing the file
+/// Load the database at the given path. Encrypted databases prompt for their
+/// password (3 attempts).
+pub fn load_db(path: &Path) -> Result<YorDb, YorError> {
+    let content = read_db_file(path)?;
+    let sealed = match content.strip_prefix(SEALED_DB_MAGIC) {
+        Some(sealed) => sealed,
+        None => return load_plain_db(path),
+    };
+    if let Some(db) = open_unlocked_db(path, sealed)? {
+        return Ok(db);
+    }
 
-                write_file(
-                    path.to_str().unwrap(),
-                    String::from_utf8(decrypted_data.unwrap()).unwrap(),
-                )
-                .unwrap();
-                data = String::from(path.to_str().unwrap());
-            } else {
-                data = String::from_utf8(decrypted_data.unwrap()).unwrap();
-            }
-        }
-        YorDataType::Str(d) => {
-            if file_types.iter().any(|&i| i == splitted_type[0]) {
-                write_file(&path.to_str().unwrap(), d).unwrap();
-                data = String::from(path.to_str().unwrap());
-            } else {
-                data = d;
-            }
+    let (key, plain) = with_password("[yor] password for the database: ", |password| {
+        let key = sealed_db_key(sealed, password);
+        key.open(sealed, Some(&sealed_db_ad()))
+            .ok()
+            .map(|plain| (key, plain))
+    })?;
+    let db = open_sealed_db(path, &plain, key)?;
+    if let Some(key) = db.seal_key() {
+        UNLOCKED_DBS
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), key.clone());
+    }
+    Ok(db)
+}
+
+/// Open the encrypted database with the key it was unlocked with by this
+/// process or by the agent, `None` if neither has it
+fn open_unlocked_db(path: &Path, sealed: &[u8]) -> Result<Option<YorDb>, YorError> {
+    let ad = sealed_db_ad();
+    // The database may be loaded more than once by a command
+    let mut unlocked = UNLOCKED_DBS.lock().unwrap();
+    if let Some(key) = unlocked.get(path) {
+        let plain = key.open(sealed, Some(&ad))?;
+        return open_sealed_db(path, &plain, key.clone()).map(Some);
+    }
+
+    // Unlocked with `yor unlock`, the agent only has the key of the salt of
+    // the database, and may have a stale one
+    let header = match parse_cipher_header(sealed) {
+        Ok(Some(header)) if header.algorithm == ALG_DBKEY_XCHACHA20POLY1305 => header,
+        _ => return Ok(None),
+    };
+    if let Some(derived) = agent::get(path, header.salt, &header.params) {
+        let key = DbKey::from_derived(header.salt, header.params, &derived);
+        if let Ok(plain) = key.open(sealed, Some(&ad)) {
+            unlocked.insert(path.to_path_buf(), key.clone());
+            return open_sealed_db(path, &plain, key).map(Some);
         }
     }
-    return data;
+    Ok(None)
 }
 
-/// Remove the given key
-pub fn rem_item(db_name: &str, key: &str) -> Result<()> {
-    let mut db = load_db(&get_db_path(&db_name)).unwrap_or_else(|_| {
-        println!("Database not found. Consider creating using `create`");
-        std::process::exit(1);
-    });
+/// The key of the encrypted json of a database for the password: the salt
+/// and parameters of its header, the older ones are decrypted with the
+/// password directly
+fn sealed_db_key(sealed: &[u8], password: &str) -> DbKey {
+    match parse_cipher_header(sealed) {
+        Ok(Some(header)) if header.algorithm == ALG_DBKEY_XCHACHA20POLY1305 => {
+            DbKey::new(password, header.salt, header.params)
+        }
+        _ => DbKey::new(password, &[], KdfParams::default()),
+    }
+}
 
-    let exists = db.exists(&key);
-    if !exists {
-        println!("Key {} Not found, perhaps it deosn't exist at all?", key);
-        std::process::exit(1);
+/// Load the database at the given path, encrypted databases are decrypted
+/// with the given password (nothing is prompted).
+pub fn load_db_with_password(path: &Path, password: &str) -> Result<YorDb, YorError> {
+    let content = read_db_file(path)?;
+    match content.strip_prefix(SEALED_DB_MAGIC) {
+        Some(sealed) => {
+            let key = sealed_db_key(sealed, password);
+            let plain = key.open(sealed, Some(&sealed_db_ad()))?;
+            open_sealed_db(path, &plain, key)
+        }
+        None => load_plain_db(path),
+    }
+}
+
+fn read_db_file(path: &Path) -> Result<Vec<u8>, YorError> {
+    fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => YorError::NotFound(String::from(
+            "Database not found. Consider creating using `create`",
+        )),
+        _ => YorError::from(e),
+    })
+}
+
+fn load_plain_db(path: &Path) -> Result<YorDb, YorError> {
+    let db = PickleDb::load_json(path, PickleDbDumpPolicy::DumpUponRequest)
+        .map_err(|_| YorError::Corrupt(String::from("Database is corrupted")))?;
+    Ok(YorDb {
+        db,
+        path: path.to_path_buf(),
+        seal_key: None,
+    })
+}
+
+/// Build the in-memory database from the decrypted json. It is encrypted
+/// with the database key when saved, the one it was decrypted with unless
+/// it was written before the database keys.
+fn open_sealed_db(path: &Path, plain: &[u8], key: DbKey) -> Result<YorDb, YorError> {
+    let corrupt = |_| YorError::Corrupt(String::from("Database is corrupted"));
+    let (map, _): (HashMap<String, String>, serde_json::Value) =
+        serde_json::from_slice(plain).map_err(corrupt)?;
+
+    let mut db = create_db(&path.to_string_lossy(), Some(key.clone()));
+    for (key, value) in map {
+        let value: serde_json::Value = serde_json::from_str(&value).map_err(corrupt)?;
+        db.set(&key, &value)?;
+    }
+    let meta = get_meta(&db);
+    let is_db_key =
+        meta.key_salt.as_deref() == Some(key.salt()) && meta.key_kdf == Some(key.params);
+    if let (false, Some(password)) = (is_db_key, key.password()) {
+        db.seal_key = Some(DbKey::for_db(&db, password)?);
+    }
+    Ok(db)
+}
+
+/// Reserved key where the database metadata is stored. Keys starting with
+/// `__yor` can't be set by the user.
+pub const META_KEY: &str = "__yor_meta";
+pub(crate) const RESERVED_PREFIX: &str = "__yor";
+
+/// Known plaintext encrypted with the master password to check it
+const DB_KEY_CHECK: &[u8] = b"yor/db_key";
+
+/// Metadata of a database, stored under `META_KEY`
+#[derive(Serialize, Deserialize, Default)]
+pub struct YorMeta {
+    /// The master password check value (`DB_KEY_CHECK` encrypted with the
+    /// master password). Databases without it use per-value passwords.
+    #[serde(default)]
+    pub db_key: Option<Vec<u8>>,
+    /// The number of earlier values kept for each key, `DEFAULT_HISTORY`
+    /// if not set
+    #[serde(default)]
+    pub history: Option<usize>,
+    /// The salt of the database key (see `DbKey`), set when the database is
+    /// first unlocked and changed by `rekey`
+    #[serde(default)]
+    pub key_salt: Option<Vec<u8>>,
+    /// The Argon2i parameters of the database key
+    #[serde(default)]
+    pub key_kdf: Option<KdfParams>,
+    /// A random id the encrypted values are bound to (see `AssociatedData`),
+    /// it stays the same when the file is copied or renamed
+    #[serde(default)]
+    pub db_id: Option<Vec<u8>>,
+}
+
+/// The number of earlier values kept for each key by default
+pub const DEFAULT_HISTORY: usize = 5;
+
+/// Reserved key where the removed keys of a database are kept, so they are
+/// protected like the rest of the database until they are purged
+pub const TRASH_KEY: &str = "__yor_trash";
+
+/// The number of days the removed keys and databases are kept by default
+pub const DEFAULT_TRASH_DAYS: u64 = 30;
+
+/// A key removed from a database, see `Vault::remove`
+#[derive(Serialize, Deserialize)]
+pub struct TrashedKey {
+    pub key: String,
+    /// When the key was removed (unix seconds)
+    pub removed_at: i64,
+    pub data: YorData,
+}
+
+/// A database moved to the trash by `trash_db`
+pub struct TrashedDb {
+    pub name: String,
+    /// When the database was deleted (unix seconds)
+    pub removed_at: i64,
+    pub path: PathBuf,
+}
+
+/// Get the removed keys of the given database, the most recent first
+pub fn get_trash(db: &YorDb) -> Vec<TrashedKey> {
+    db.get::<Vec<TrashedKey>>(TRASH_KEY).unwrap_or_default()
+}
+
+/// The number of days the removed keys and databases are kept before they
+/// are purged, 0 keeps them until the trash is emptied
+pub fn trash_days() -> u64 {
+    load_db(&vault_root().join("config"))
+        .ok()
+        .and_then(|conf| conf.get::<u64>("trash_days"))
+        .unwrap_or(DEFAULT_TRASH_DAYS)
+}
+
+/// The given number of days in seconds, `None` if it doesn't fit
+pub fn trash_age(days: u64) -> Option<i64> {
+    i64::try_from(days).ok()?.checked_mul(24 * 60 * 60)
+}
+
+/// Check if something removed at the given time is older than the trash age
+pub fn is_trash_expired(removed_at: i64) -> bool {
+    let days = trash_days();
+    // An age that doesn't fit is never reached
+    days > 0 && trash_age(days).is_some_and(|age| now().saturating_sub(removed_at) > age)
+}
+
+/// Get the directory of the deleted databases
+pub fn get_trash_dir() -> PathBuf {
+    vault_root().join("trash")
+}
+
+/// Move the database with the given name to the trash, the databases older
+/// than the trash age are purged. The trashed file is named
+/// `<removed at>.<random hex>-<name>`, a database deleted twice in the same
+/// second doesn't replace the first one.
+pub fn trash_db(name: &str) -> Result<(), YorError> {
+    let path = get_db_path(name);
+    if !path.exists() {
+        return Err(YorError::NotFound(format!(
+            "Database {} doesn't exist at all",
+            name
+        )));
+    }
+    let trash_dir = get_trash_dir();
+    fs::create_dir_all(&trash_dir)?;
+    let suffix: String = salt()?[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    let trashed = trash_dir.join(format!("{}.{}-{}", now(), suffix, name));
+    if trashed.exists() {
+        return Err(YorError::Invalid(format!(
+            "Database: {} is already in the trash: {}",
+            name,
+            trashed.display()
+        )));
+    }
+    fs::rename(&path, trashed)?;
+    purge_db_trash(false)?;
+    Ok(())
+}
+
+/// Get the deleted databases, the most recent first
+pub fn get_trashed_dbs() -> Vec<TrashedDb> {
+    let mut dbs: Vec<TrashedDb> = fs::read_dir(get_trash_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            // `<removed at>-<name>` before the random suffix
+            let (prefix, name) = file_name.split_once('-')?;
+            let removed_at = prefix.split_once('.').map_or(prefix, |(time, _)| time);
+            Some(TrashedDb {
+                name: name.to_string(),
+                removed_at: removed_at.parse().ok()?,
+                path: entry.path(),
+            })
+        })
+        .collect();
+    dbs.sort_by_key(|db| std::cmp::Reverse(db.removed_at));
+    dbs
+}
+
+/// Restore the most recently deleted database with the given name
+pub fn restore_db(name: &str) -> Result<(), YorError> {
+    let trashed = get_trashed_dbs()
+        .into_iter()
+        .find(|db| db.name == name)
+        .ok_or_else(|| YorError::NotFound(format!("Database: {} is not in the trash", name)))?;
+    let path = get_db_path(name);
+    if path.exists() {
+        return Err(YorError::Invalid(format!(
+            "It looks like database: {} is already created.",
+            name
+        )));
+    }
+    fs::rename(&trashed.path, &path)?;
+    Ok(())
+}
+
+/// Remove the deleted databases for good, only the ones older than the trash
+/// age unless `all` is set. Their files are left to `sweep_blobs`. Returns
+/// the number of removed databases.
+pub fn purge_db_trash(all: bool) -> Result<usize, YorError> {
+    let mut count = 0;
+    for db in get_trashed_dbs() {
+        if all || is_trash_expired(db.removed_at) {
+            fs::remove_file(&db.path)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Remove the files of the blob store that no database refers to: the
+/// values, earlier values and removed keys of every database (the deleted
+/// ones included) are counted. Nothing is removed if one of the databases is
+/// encrypted and locked (neither unlocked by this process nor by the agent),
+/// as its files are unknown. Returns the number of removed files.
+pub fn sweep_blobs() -> Result<usize, YorError> {
+    let files = |dir: PathBuf| {
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_file())
+    };
+    let mut used = HashSet::new();
+    for entry in files(vault_root().join("db")).chain(files(get_trash_dir())) {
+        let path = entry.path();
+        // A database being written, or a lock file (see `lock_db_file`)
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || path.extension().is_some_and(|ext| ext == "tmp") {
+            continue;
+        }
+        let content = read_db_file(&path)?;
+        let db = match content.strip_prefix(SEALED_DB_MAGIC) {
+            Some(sealed) => open_unlocked_db(&path, sealed)?,
+            None => Some(load_plain_db(&path)?),
+        };
+        let db = db.ok_or_else(|| {
+            YorError::Invalid(format!(
+                "Database: {} is locked, unlock it to remove the unused files",
+                entry.file_name().to_string_lossy()
+            ))
+        })?;
+        for key in get_keys(&db) {
+            if let Some(yor) = db.get::<YorData>(&key) {
+                used.extend(yor.blob_ids());
+            }
+        }
+        for trashed in get_trash(&db) {
+            used.extend(trashed.data.blob_ids());
+        }
+    }
+
+    let mut count = 0;
+    for entry in files(blob::get_blob_dir()) {
+        let id = entry.file_name().to_string_lossy().into_owned();
+        // The temporary files of the blobs being written
+        if id.starts_with('.') || used.contains(&id) {
+            continue;
+        }
+        fs::remove_file(entry.path())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Get the metadata of the given database
+pub fn get_meta(db: &YorDb) -> YorMeta {
+    db.get::<YorMeta>(META_KEY).unwrap_or_default()
+}
+
+/// Check if the given key is reserved for yor internals
+pub fn is_reserved_key(key: &str) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+/// Get all the user keys of the database (without the reserved ones), sorted
+pub fn get_keys(db: &YorDb) -> Vec<String> {
+    let mut keys: Vec<String> = db
+        .get_all()
+        .into_iter()
+        .filter(|k| !is_reserved_key(k))
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// Set the master password of the database, every protected value of the
+/// database is expected to be encrypted with it afterwards.
+///
+/// # Arguments
+/// - `db` - The database
+/// - `password` - The master password
+pub fn set_db_key(db: &mut YorDb, key: &DbKey) -> Result<(), YorError> {
+    let mut meta = get_meta(db);
+    meta.db_key = Some(key.encrypt(DB_KEY_CHECK, &db_key_check_ad(db))?);
+    db.set(META_KEY, &meta)?;
+    Ok(())
+}
+
+/// Store the salt and the KDF parameters of the key as the ones of the
+/// database key, unless they already are. The database isn't saved.
+pub fn set_key_salt(db: &mut YorDb, key: &DbKey) -> Result<(), YorError> {
+    let mut meta = get_meta(db);
+    if meta.key_salt.as_ref() == Some(&key.salt) && meta.key_kdf == Some(key.params) {
+        return Ok(());
+    }
+    meta.key_salt = Some(key.salt.clone());
+    meta.key_kdf = Some(key.params);
+    db.set(META_KEY, &meta)?;
+    Ok(())
+}
+
+/// Give the database a random id unless it has one, see `YorMeta::db_id`.
+/// The database isn't saved.
+pub fn set_db_id(db: &mut YorDb) -> Result<(), YorError> {
+    let mut meta = get_meta(db);
+    if meta.db_id.is_some() {
+        return Ok(());
+    }
+    meta.db_id = Some(salt()?.to_vec());
+    db.set(META_KEY, &meta)?;
+    Ok(())
+}
+
+/// The master password check value isn't bound to the id of its database,
+/// the ones of `CIPHER_VERSION_BOUND` are bound to its name (see
+/// `Vault::set_key`)
+fn db_key_check_ad(db: &YorDb) -> AssociatedData {
+    AssociatedData {
+        db_id: Vec::new(),
+        ..AssociatedData::new(db, META_KEY, "db_key")
+    }
+}
+
+/// Check the key against the master password of the database.
+/// Returns `false` if the database has no master password.
+pub fn verify_db_key(db: &YorDb, key: &DbKey) -> bool {
+    match get_meta(db).db_key {
+        Some(check) => key
+            .decrypt(check, &db_key_check_ad(db))
+            .map(|plain| plain == DB_KEY_CHECK)
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+/// Prompt for the master password of the database (3 attempts) and return
+/// its key. Returns `None` if the database has no master password. The
+/// password of an encrypted database is its master password so it isn't
+/// asked again.
+pub fn unlock_db(db: &YorDb) -> Result<Option<DbKey>, YorError> {
+    if get_meta(db).db_key.is_none() {
+        return Ok(None);
+    }
+    let check = |password: &str| {
+        DbKey::for_db(db, password)
+            .ok()
+            .filter(|key| verify_db_key(db, key))
+    };
+    if let Some(key) = db.seal_key.clone().filter(|key| verify_db_key(db, key)) {
+        return Ok(Some(key));
+    }
+    if let Some(key) = agent_key(db).filter(|key| verify_db_key(db, key)) {
+        return Ok(Some(key));
+    }
+
+    with_password("[yor] master password for the database: ", check).map(Some)
+}
+
+/// The key of the database handed to the agent by `yor unlock`, for the
+/// salt and KDF parameters of its metadata. It isn't checked.
+pub fn agent_key(db: &YorDb) -> Option<DbKey> {
+    let meta = get_meta(db);
+    let (salt, params) = (meta.key_salt?, meta.key_kdf?);
+    let key = agent::get(db.path(), &salt, &params)?;
+    Some(DbKey::from_derived(&salt, params, &key))
+}
+
+/// Name of the project-local vault directory and config file
+pub const PROJECT_VAULT: &str = ".yor";
+pub const PROJECT_CONFIG: &str = ".yor.toml";
+
+/// A project-local vault, see `find_project`
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// The `.yor` directory or `.yor.toml` file that was found
+    pub path: PathBuf,
+    /// The vault root of the project, the default one is used without it
+    pub root: Option<PathBuf>,
+    /// The database to use instead of the current one of the vault
+    pub db: Option<String>,
+}
+
+/// Content of a `.yor.toml`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ProjectConfig {
+    /// The vault root, relative to the directory of the file
+    vault: Option<PathBuf>,
+    /// The database to use
+    db: Option<String>,
+}
+
+/// Find the project-local vault of the given directory, like git finds
+/// `.git`: the directory and each of its parents are searched for a
+/// `.yor.toml` or a `.yor` vault. The `~/.yor` of the older versions isn't
+/// a project vault.
+pub fn find_project(start: &Path) -> Result<Option<Project>, YorError> {
+    let legacy = legacy_vault_root();
+    for dir in start.ancestors() {
+        let config = dir.join(PROJECT_CONFIG);
+        if config.is_file() {
+            let content = fs::read_to_string(&config)?;
+            let parsed: ProjectConfig = toml::from_str(&content).map_err(|e| {
+                YorError::Invalid(format!("Invalid config: {} ({})", config.display(), e))
+            })?;
+            return Ok(Some(Project {
+                root: parsed.vault.map(|vault| dir.join(vault)),
+                db: parsed.db,
+                path: config,
+            }));
+        }
+        let vault = dir.join(PROJECT_VAULT);
+        if vault.is_dir() && Some(&vault) != legacy.as_ref() {
+            return Ok(Some(Project {
+                path: vault.clone(),
+                root: Some(vault),
+                db: None,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Where the vault root comes from
+#[derive(Debug, Clone)]
+pub enum VaultSource {
+    /// The `--home` flag
+    Flag,
+    /// The `YOR_HOME` variable
+    Env,
+    /// A project-local vault
+    Project(Project),
+    /// The default vault root (see `default_vault_root`)
+    Default,
+}
+
+/// The directory of the databases, files and config, resolved once
+static VAULT_ROOT: OnceLock<(PathBuf, VaultSource)> = OnceLock::new();
+
+/// Set the vault root (the `--home` flag). Without it, `YOR_HOME` is used
+/// when it is set, then the project-local vault of the current directory
+/// (see `find_project`), otherwise `$XDG_DATA_HOME/yor` (see
+/// `default_vault_root`). It must be called before any path of the vault is
+/// used.
+pub fn set_vault_root(path: Option<PathBuf>) -> Result<(), YorError> {
+    let resolved = match path {
+        Some(path) => (path, VaultSource::Flag),
+        None => resolve_vault_root()?,
+    };
+    let _ = VAULT_ROOT.set(resolved);
+    Ok(())
+}
+
+fn resolve_vault_root() -> Result<(PathBuf, VaultSource), YorError> {
+    if let Some(path) = std::env::var_os("YOR_HOME") {
+        return Ok((PathBuf::from(path), VaultSource::Env));
+    }
+    let project = match std::env::current_dir() {
+        Ok(dir) => find_project(&dir)?,
+        Err(_) => None,
+    };
+    Ok(match project {
+        Some(project) => (
+            project.root.clone().unwrap_or_else(default_vault_root),
+            VaultSource::Project(project),
+        ),
+        None => (default_vault_root(), VaultSource::Default),
+    })
+}
+
+fn resolved_vault_root() -> &'static (PathBuf, VaultSource) {
+    VAULT_ROOT.get_or_init(|| {
+        resolve_vault_root().unwrap_or_else(|_| (default_vault_root(), VaultSource::Default))
+    })
+}
+
+/// Get the vault root, every path of the environment is inside it
+pub fn vault_root() -> &'static Path {
+    &resolved_vault_root().0
+}
+
+/// Get where the vault root comes from
+pub fn vault_source() -> &'static VaultSource {
+    &resolved_vault_root().1
+}
+
+/// The vault root when none is given: `yor` in the data directory of the
+/// platform (`$XDG_DATA_HOME/yor` or `~/.local/share/yor` on linux)
+pub fn default_vault_root() -> PathBuf {
+    dirs::data_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+        .unwrap_or_default()
+        .join("yor")
+}
+
+/// The vault root used before it was configurable
+fn legacy_vault_root() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".yor"))
+}
+
+/// Move the vault of the older versions (`~/.yor`) to the default vault
+/// root. Nothing is moved if the vault root is set or already exists.
+fn migrate_legacy_root(root: &Path) -> Result<(), YorError> {
+    let legacy = match legacy_vault_root() {
+        Some(legacy) => legacy,
+        None => return Ok(()),
+    };
+    if root != default_vault_root() || root.exists() || !legacy.is_dir() {
+        return Ok(());
+    }
+
+    if let Some(parent) = root.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&legacy, root).map_err(|e| {
+        YorError::Io(format!(
+            "Cannot move the vault from {} to {} ({}), move it or set YOR_HOME to it",
+            legacy.display(),
+            root.display(),
+            e
+        ))
+    })?;
+
+    // The file environment is stored as an absolute path
+    let mut conf = get_config_data()?;
+    if let Some(file_env) = conf.get::<String>("file_env") {
+        if let Ok(rest) = Path::new(&file_env).strip_prefix(&legacy) {
+            conf.set("file_env", &root.join(rest).to_string_lossy().to_string())?;
+            conf.save()?;
+        }
+    }
+    warn(format!(
+        "The vault was moved from {} to {}",
+        legacy.display(),
+        root.display()
+    ));
+    Ok(())
+}
+
+fn init_config_db(env: &Path) -> Result<(), YorError> {
+    if !env.join("config").as_path().exists() {
+        let mut db = create_db(&env.join("config").to_string_lossy(), None);
+
+        db.set("db_name", &String::from("default"))?;
+        // Relative to the vault root so the vault can be moved
+        db.set("file_env", &String::from("files"))?;
+        db.save()?;
+    }
+    Ok(())
+}
+
+pub fn initialize_env() -> Result<(), YorError> {
+    let env = vault_root();
+    migrate_legacy_root(env)?;
+    init_vault(env)
+}
+
+/// Create the directories, config and default database of the vault at the
+/// given root, the existing ones are kept.
+pub fn init_vault(env: &Path) -> Result<(), YorError> {
+    let db_path = env.join("db");
+    let default_db = db_path.as_path().join("default");
+    let file_path = env.join("files");
+
+    fs::create_dir_all(env)?;
+    fs::create_dir_all(db_path)?;
+    fs::create_dir_all(file_path)?;
+    fs::create_dir_all(env.join("blobs"))?;
+    init_config_db(env)?;
+
+    // Initialize default db
+
+    if !default_db.exists() {
+        create_db(&default_db.to_string_lossy(), None).save()?;
+    }
+
+    Ok(())
+}
+
+/// Get the config data.
+/// # Return (tuple)
+/// - `key` - The password key of the given database
+/// - `db_name` - The name of the database stored
+pub fn get_config_data() -> Result<YorDb, YorError> {
+    let cfg_path = vault_root().join("config");
+    load_db(cfg_path.as_path())
+        .map_err(|_| YorError::Corrupt(String::from("Cannot read the config of yor")))
+}
+
+/// Get the name of the current database, the one of the project if it sets
+/// one, otherwise the one of the config
+pub fn get_db_name() -> Result<String, YorError> {
+    if let VaultSource::Project(Project { db: Some(db), .. }) = vault_source() {
+        return Ok(db.clone());
+    }
+    get_config_data()?
+        .get::<String>("db_name")
+        .ok_or_else(|| YorError::Corrupt(String::from("The current database is not set")))
+}
+
+/// Get the db path from the environment given the name
+///
+/// # Arguments
+/// - `name` - The name of the database
+pub fn get_db_path(name: &str) -> PathBuf {
+    vault_root().join("db").join(name)
+}
+
+/// A database of the environment, see `get_all_db`
+pub struct DbInfo {
+    pub name: String,
+    /// The whole database is encrypted
+    pub encrypted: bool,
+    /// It is the current database
+    pub current: bool,
+}
+
+/// Get all the database that can be found from the environment
+/// directories, sorted by name
+pub fn get_all_db() -> Result<Vec<DbInfo>, YorError> {
+    let db_path = vault_root().join("db");
+    let default_db_name = get_db_name()?;
+
+    let mut dbs = Vec::new();
+    if let Ok(entries) = fs::read_dir(db_path) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // The lock files (see `lock_db_file`)
+            if name.starts_with('.') {
+                continue;
+            }
+            dbs.push(DbInfo {
+                encrypted: is_sealed_db(&entry.path()),
+                current: name == default_db_name,
+                name,
+            });
+        }
+    }
+    dbs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(dbs)
+}
+
+/// Get all the files that can be found from the environment
+/// directories, sorted by name
+pub fn get_all_files() -> Result<Vec<String>, YorError> {
+    let db_path = vault_root().join("files");
+
+    let mut files = Vec::new();
+    if let Ok(entries) = fs::read_dir(db_path) {
+        for entry in entries.flatten() {
+            files.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn split_type(string: &str) -> Vec<&str> {
+    string.split("/").collect()
+}
+
+/// Get the value of the given key, the password is prompted if needed.
+/// Files are written to the file environment (or `out`) and their path is
+/// returned. The value is returned as is, it may not be valid UTF-8.
+///
+/// # Arguments
+/// - `db_name` - The name of the database (default)
+/// - `key` - The given key for the value to get
+/// - `out` - Where to write the file or the value
+pub fn get_item(db_name: &str, key: &str, out: Option<String>) -> Result<Vec<u8>, YorError> {
+    read_item(&mut Vault::load(db_name)?, key, out)
+}
+
+/// Get the value of the given key from the vault, see `get_item`
+fn read_item(vault: &mut Vault, key: &str, out: Option<String>) -> Result<Vec<u8>, YorError> {
+    // `<record>.<field>` is a field of a record
+    if let Some((record, field)) = vault.split_field(key) {
+        if vault.field(record, field)?.data.is_encrypted() {
+            vault.prompt_unlock_key(record)?;
+        }
+        let value = vault.get_field(record, field)?;
+        let _ = vault.touch(&[record]);
+        if let Some(out) = out {
+            fs::write(&out, value)
+                .map_err(|e| YorError::Io(format!("Cannot write the file: {} ({})", out, e)))?;
+            return Ok(out.into_bytes());
+        }
+        return Ok(value);
+    }
+    let yor = vault.entry(key)?;
+    vault.prompt_unlock_key(key)?;
+    if !yor.is_file() && out.is_none() {
+        let mut value = vault.get(key)?;
+        // The otp secrets are shown as otpauth:// URIs
+        if split_type(&yor.y_type)[0] == "otp" {
+            value = serde_json::from_slice::<Otp>(&value)?.to_uri().into_bytes();
+        }
+        // The access time is best effort, a read-only vault can still be read
+        let _ = vault.touch(&[key]);
+        return Ok(value);
+    }
+
+    let mut splitted_type = split_type(&yor.y_type);
+    splitted_type.resize(2, "");
+    let configdb = get_config_data()?;
+
+    let pathstr = configdb
+        .get::<String>("file_env")
+        .ok_or_else(|| YorError::Corrupt(String::from("The file environment is not set")))?;
+    // A relative file environment is in the vault root
+    let file_env = vault_root().join(pathstr);
+    let mut path = file_env.join(format!("{}.{}", &key, splitted_type[1]));
+
+    if splitted_type[1] == "bin" {
+        path = file_env.join(key);
+    }
+
+    if let Some(o) = out {
+        path = Path::new(&o).to_path_buf();
+    }
+
+    vault.restore(key, &path)?;
+    let _ = vault.touch(&[key]);
+    Ok(path.to_string_lossy().into_owned().into_bytes())
+}
+
+/// Get the fields of a record, the sensitive ones are masked (`None`) unless
+/// `reveal` is set, the password is prompted then. Returns `None` if the key
+/// isn't a record.
+///
+/// # Arguments
+/// - `db_name` - The name of the database
+/// - `key` - The key of the record
+/// - `reveal` - Decrypt the sensitive fields
+pub fn get_record(
+    db_name: &str,
+    key: &str,
+    reveal: bool,
+) -> Result<Option<RecordFields>, YorError> {
+    let mut vault = Vault::load(db_name)?;
+    // Anything else (a field of a record included) is left to `get_item`
+    if !vault.contains(key) || !vault.entry(key)?.is_record() {
+        return Ok(None);
+    }
+    if reveal {
+        vault.prompt_unlock_key(key)?;
+    }
+    let fields = vault.fields(key, reveal)?;
+    let _ = vault.touch(&[key]);
+    Ok(Some(fields))
+}
+
+/// Re-encrypt the password protected entries of the database with a new
+/// key. Nothing is written if an entry can't be decrypted with the old
+/// key, and the database is saved once at the end so it never ends up
+/// with mixed passwords. The master password check, the salt of the
+/// database key and the encryption at rest of the database are rotated too.
+///
+/// # Arguments
+/// - `db` - The database
+/// - `old` - The current key
+/// - `new` - The new key, with a new salt
+/// - `filters` - The keys to rotate (see `key_matches`), must be empty if the
+///   database has a master password
+///
+/// # Returns
+/// The number of rotated entries
+pub fn rekey(
+    db: &mut YorDb,
+    old: &DbKey,
+    new: &DbKey,
+    filters: &[String],
+) -> Result<usize, YorError> {
+    let has_db_key = get_meta(db).db_key.is_some();
+    if has_db_key && !filters.is_empty() {
+        return Err(YorError::Invalid(String::from(
+            "Every key of a database with a master password must use the same password",
+        )));
+    }
+    set_db_id(db)?;
+
+    let mut rotated = Vec::new();
+    let mut trash = get_trash(db);
+    let mut trash_rotated = false;
+    let mut old_blobs = Vec::new();
+    let mut new_blobs = Vec::new();
+    let result = (|| -> Result<()> {
+        // The values of older formats are upgraded on the way
+        let mut rotate = |data: &YorDataType, ad: AssociatedData| -> Result<Option<YorDataType>> {
+            match data {
+                YorDataType::Bytes(d) => {
+                    let plain = old.decrypt(d, &ad)?;
+                    Ok(Some(YorDataType::Bytes(new.encrypt(plain, &ad)?)))
+                }
+                YorDataType::Blob(b) if b.encrypted => {
+                    let reader = blob::open_blob(b, Some(old), &ad)?;
+                    let rotated_blob = blob::store_blob(reader, Some(new), &ad)?;
+                    old_blobs.push(b.clone());
+                    new_blobs.push(rotated_blob.clone());
+                    Ok(Some(YorDataType::Blob(rotated_blob)))
+                }
+                YorDataType::Record(fields) if data.is_encrypted() => {
+                    let mut fields = fields.clone();
+                    for field in fields.iter_mut() {
+                        if let YorDataType::Bytes(d) = &field.data {
+                            let ad = ad.clone().field(&field.name);
+                            field.data =
+                                YorDataType::Bytes(new.encrypt(old.decrypt(d, &ad)?, &ad)?);
+                        }
+                    }
+                    Ok(Some(YorDataType::Record(fields)))
+                }
+                _ => Ok(None),
+            }
+        };
+        // Rotate the value and the earlier values of an entry, returns `true`
+        // if anything changed. Without a master password, the earlier values
+        // (and the removed keys) may have been set with another password and
+        // are left as they are.
+        let mut rotate_entry = |key: &str, yor: &mut YorData, strict: bool| -> Result<bool> {
+            let mut changed = false;
+            match rotate(&yor.y_data, AssociatedData::new(db, key, &yor.y_type)) {
+                Ok(Some(data)) => {
+                    yor.y_data = data;
+                    changed = true;
+                }
+                Ok(None) => {}
+                Err(e) if strict => return Err(e),
+                Err(_) => {}
+            }
+            for earlier in yor.history.iter_mut() {
+                match rotate(
+                    &earlier.y_data,
+                    AssociatedData::new(db, key, &earlier.y_type),
+                ) {
+                    Ok(Some(data)) => {
+                        earlier.y_data = data;
+                        changed = true;
+                    }
+                    Ok(None) => {}
+                    Err(e) if has_db_key => return Err(e),
+                    Err(_) => {}
+                }
+            }
+            Ok(changed)
+        };
+
+        for key in get_keys(db) {
+            if !key_matches(&key, filters) {
+                continue;
+            }
+            let mut yor = match db.get::<YorData>(&key) {
+                Some(yor) => yor,
+                None => continue,
+            };
+            let error = || format!("Cannot decrypt the key: {} with the password", key);
+            if rotate_entry(&key, &mut yor, true).with_context(error)? {
+                rotated.push((key, yor));
+            }
+        }
+        for trashed in trash.iter_mut() {
+            if !key_matches(&trashed.key, filters) {
+                continue;
+            }
+            let error = || {
+                format!(
+                    "Cannot decrypt the removed key: {} with the password",
+                    trashed.key
+                )
+            };
+            trash_rotated |=
+                rotate_entry(&trashed.key, &mut trashed.data, has_db_key).with_context(error)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        // The database is untouched, drop the blobs written so far
+        for b in new_blobs {
+            let _ = blob::remove_blob(&b);
+        }
+        return Err(e.into());
+    }
+
+    let count = rotated.len();
+    for (key, yor) in rotated {
+        db.set(&key, &yor)?;
+    }
+    if trash_rotated {
+        db.set(TRASH_KEY, &trash)?;
+    }
+    set_key_salt(db, new)?;
+    if has_db_key {
+        set_db_key(db, new)?;
+    }
+    if db.seal_key.is_some() {
+        db.seal_key = Some(new.clone());
+    }
+    db.save()?;
+    for b in old_blobs {
+        let _ = blob::remove_blob(&b);
+    }
+    Ok(count)
+}
+
+/// Shells supported by `load-env`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Pwsh,
+}
+
+impl Shell {
+    /// Get the shell from the given name (bash, zsh, fish or powershell)
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name.to_lowercase().as_str() {
+            "bash" | "sh" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "powershell" | "pwsh" => Some(Shell::Pwsh),
+            _ => None,
+        }
+    }
+
+    /// Detect the shell from the `$SHELL` variable, falls back to bash
+    /// (or Pwsh on windows)
+    pub fn detect() -> Shell {
+        let fallback = if cfg!(windows) {
+            Shell::Pwsh
+        } else {
+            Shell::Bash
+        };
+        std::env::var("SHELL")
+            .ok()
+            .and_then(|s| {
+                Path::new(&s)
+                    .file_name()
+                    .and_then(|n| Shell::from_name(&n.to_string_lossy()))
+            })
+            .unwrap_or(fallback)
+    }
+
+    /// Format an export statement of the given variable for the shell.
+    /// The value is always single-quoted so nothing gets expanded by `eval`.
+    pub fn export(&self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => {
+                format!("export {}='{}'", name, value.replace('\'', r"'\''"))
+            }
+            Shell::Fish => format!(
+                "set -gx {} '{}'",
+                name,
+                value.replace('\\', r"\\").replace('\'', r"\'")
+            ),
+            Shell::Pwsh => format!("$env:{} = '{}'", name, value.replace('\'', "''")),
+        }
+    }
+}
+
+/// Check if the given key can be used as an environment variable name
+fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn warn(message: String) {
+    eprintln!("{}", message.truecolor(157, 123, 125));
+}
+
+/// Check if the key matches any of the given filters. A filter ending with
+/// `*` matches every key starting with it, an empty filter list matches all.
+fn key_matches(key: &str, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters.iter().any(|f| match f.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == f,
+        })
+}
+
+/// Read all the data entries (no files, images or videos) of the given
+/// database that matches the filters as `(key, value)` pairs.
+/// Password protected entries are decrypted with a single password prompt,
+/// entries that cannot be decrypted are skipped with a warning.
+///
+/// # Arguments
+/// - `db_name` - The name of the database
+/// - `filters` - The keys to read (see `key_matches`)
+fn read_env_entries(db_name: &str, filters: &[String]) -> Result<Vec<(String, String)>, YorError> {
+    let mut vault = Vault::load(db_name)?;
+    vault.prompt_unlock()?;
+
+    let mut entries = Vec::new();
+    let items: Vec<(String, YorData)> = vault.iter().collect();
+    for (key, yor) in items {
+        if !key_matches(&key, filters) || yor.is_file() {
+            continue;
+        }
+        if yor.is_expired() {
+            warn(format!("Skipping key: {}, it is expired.", key));
+            continue;
+        }
+        if split_type(&yor.y_type)[0] == "otp" {
+            warn(format!("Skipping key: {}, it is an otp secret.", key));
+            continue;
+        }
+        if yor.is_record() {
+            warn(format!("Skipping key: {}, it is a record.", key));
+            continue;
+        }
+        if yor.is_encrypted() && !vault.is_unlocked() {
+            vault.unlock(&get_password("[yor] password for the database: ")?)?;
+        }
+
+        match vault.get_string(&key) {
+            Ok(value) => entries.push((key, value)),
+            Err(YorError::Invalid(_)) => {
+                warn(format!("Skipping key: {}, it is not valid UTF-8.", key));
+            }
+            Err(YorError::WrongPassword) => warn(format!(
+                "Skipping key: {}, the password is invalid for it.",
+                key
+            )),
+            Err(e) => warn(format!("Skipping key: {}: {}", key, e)),
+        }
+    }
+    let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+    let _ = vault.touch(&keys);
+    Ok(entries)
+}
+
+/// Get all the keys of the given database as shell `export` statements,
+/// meant to be printed for `eval "$(yor load-env)"`.
+/// File, image and video entries are skipped. Password protected entries are
+/// decrypted with a single password prompt.
+///
+/// # Arguments
+/// - `db_name` - The name of the database
+/// - `shell` - The shell to format the statements for
+pub fn load_env(db_name: &str, shell: Shell) -> Result<Vec<String>, YorError> {
+    let mut exports = Vec::new();
+    for (key, value) in read_env_entries(db_name, &[])? {
+        if !is_valid_env_name(&key) {
+            warn(format!(
+                "Skipping key: {}, it is not a valid environment variable name.",
+                key
+            ));
+            continue;
+        }
+        exports.push(shell.export(&key, &value));
+    }
+    Ok(exports)
+}
+
+/// Map a key to an environment variable name. The first matching
+/// `key-prefix=ENV_PREFIX` mapping is applied, then everything that isn't
+/// alphanumeric is replaced by `_` and the name is uppercased.
+/// e.g. `db.password` -> `DB_PASSWORD`
+///
+/// # Arguments
+/// - `key` - The key to map
+/// - `maps` - The prefix mappings, formatted as `from=to`
+fn env_name_for_key(key: &str, maps: &[String]) -> String {
+    let mut name = key.to_string();
+    for map in maps {
+        if let Some((from, to)) = map.split_once('=') {
+            if let Some(rest) = key.strip_prefix(from) {
+                name = format!("{}{}", to, rest);
+                break;
+            }
+        }
+    }
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Run the given command with the keys of the database injected into its
+/// environment and return its exit code.
+///
+/// # Arguments
+/// - `db_name` - The name of the database
+/// - `filters` - The keys to inject (see `key_matches`)
+/// - `maps` - The prefix mappings for the variable names (see `env_name_for_key`)
+/// - `command` - The program and its arguments
+pub fn exec(
+    db_name: &str,
+    filters: &[String],
+    maps: &[String],
+    command: &[String],
+) -> Result<i32, YorError> {
+    let vars: Vec<(String, String)> = read_env_entries(db_name, filters)?
+        .into_iter()
+        .map(|(key, value)| (env_name_for_key(&key, maps), value))
+        .collect();
+
+    let status = match std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .envs(vars)
+        .status()
+    {
+        Ok(status) => status,
+        Err(e) => {
+            warn(format!("Cannot run `{}`: {}", command[0], e));
+            return Ok(127);
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Ok(128 + signal);
+        }
+    }
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The vault root of the tests, set once for the whole process
+    fn test_vault_root() -> &'static Path {
+        static ROOT: OnceLock<PathBuf> = OnceLock::new();
+        ROOT.get_or_init(|| {
+            let root = std::env::temp_dir().join(format!("yor-test-{}", std::process::id()));
+            set_vault_root(Some(root.clone())).unwrap();
+            assert_eq!(vault_root(), root);
+            init_vault(&root).unwrap();
+            root
+        })
+    }
+
+    #[test]
+    fn protected_file_round_trip() {
+        let root = test_vault_root();
+        let input = root.join("input.bin");
+        let out = root.join("output.bin");
+        // Spans a few chunks and ends with a partial one
+        let content: Vec<u8> = (0..=255u8).cycle().take(200 * 1024 + 7).collect();
+        fs::write(&input, &content).unwrap();
+
+        let path = get_db_path("protected_files");
+        let mut vault = Vault::create(&path, Some("hunter2"), false).unwrap();
+        vault
+            .set_file("backup", &input, "file/bin", &Default::default())
+            .unwrap();
+        let stored = match vault.entry("backup").unwrap().y_data {
+            YorDataType::Blob(b) => b,
+            _ => unreachable!(),
+        };
+        assert!(stored.encrypted);
+        assert_eq!(stored.size, content.len() as u64);
+        let raw = fs::read(blob::get_blob_path(&stored.id)).unwrap();
+        assert!(!raw.windows(256).any(|w| w == &content[..256]));
+
+        // Read back from the database file, like `yor get`
+        assert!(matches!(
+            Vault::open(&path, Some("wrong")),
+            Err(YorError::WrongPassword)
+        ));
+        let mut vault = Vault::open(&path, Some("hunter2")).unwrap();
+        let restored = read_item(&mut vault, "backup", None).unwrap();
+        let restored = PathBuf::from(String::from_utf8(restored).unwrap());
+        assert_eq!(restored, root.join("files").join("backup"));
+        assert_eq!(fs::read(&restored).unwrap(), content);
+        let given = Some(out.to_string_lossy().into_owned());
+        read_item(&mut vault, "backup", given).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), content);
+        fs::remove_file(&out).unwrap();
+
+        // The blob referenced from another key
+        let key = DbKey::for_db(vault.db(), "hunter2").unwrap();
+        let ad = AssociatedData::new(vault.db(), "backup", "file/bin");
+        assert!(blob::open_blob(&stored, Some(&key), &ad).is_ok());
+        let moved = AssociatedData::new(vault.db(), "copy", "file/bin");
+        let error = blob::open_blob(&stored, Some(&key), &moved).err().unwrap();
+        assert!(matches!(YorError::from(error), YorError::Corrupt(_)));
+
+        // Without a master password, a wrong password only fails on the file
+        let path = get_db_path("protected_files_per_key");
+        let mut vault = Vault::create(&path, None, false).unwrap();
+        vault.unlock("hunter2").unwrap();
+        vault
+            .set_file("backup", &input, "file/bin", &Default::default())
+            .unwrap();
+        let mut vault = Vault::open(&path, Some("wrong")).unwrap();
+        let given = Some(out.to_string_lossy().into_owned());
+        assert!(matches!(
+            read_item(&mut vault, "backup", given),
+            Err(YorError::WrongPassword)
+        ));
+        assert!(!out.exists());
+
+        fs::remove_file(input).unwrap();
+        fs::remove_file(get_db_path("protected_files")).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn swapped_ciphertexts_are_rejected() {
+        let mut db = create_db("work", None);
+        set_db_id(&mut db).unwrap();
+        let key = DbKey::new("hunter2", &salt().unwrap(), KdfParams::default());
+        for (name, value) in [("aws_prod", "prod-secret"), ("aws_dev", "dev-secret")] {
+            let ad = AssociatedData::new(&db, name, "data/byte");
+            let data = YorDataType::Bytes(key.encrypt(value, &ad).unwrap());
+            db.set(name, &YorData::new(data, String::from("data/byte")))
+                .unwrap();
+        }
+        let ciphertext = |db: &YorDb, name: &str| match db.get::<YorData>(name).unwrap().y_data {
+            YorDataType::Bytes(d) => d,
+            _ => unreachable!(),
+        };
+        let prod = ciphertext(&db, "aws_prod");
+        let dev = ciphertext(&db, "aws_dev");
+        let prod_ad = AssociatedData::new(&db, "aws_prod", "data/byte");
+        assert_eq!(key.decrypt(&prod, &prod_ad).unwrap(), b"prod-secret");
+        assert!(!is_outdated(&prod));
+
+        // Swap the encrypted values of the two keys, as an edit of the file would
+        let mut swapped = db.get::<YorData>("aws_prod").unwrap();
+        swapped.y_data = YorDataType::Bytes(dev.clone());
+        db.set("aws_prod", &swapped).unwrap();
+        assert!(matches!(
+            key.decrypt(ciphertext(&db, "aws_prod"), &prod_ad),
+            Err(YorError::Corrupt(_))
+        ));
+        let dev_ad = AssociatedData::new(&db, "aws_dev", "data/byte");
+        assert!(matches!(
+            key.decrypt(&prod, &dev_ad),
+            Err(YorError::Corrupt(_))
+        ));
+        // Unlike a wrong password
+        let wrong = DbKey::new("hunter3", key.salt(), KdfParams::default());
+        assert!(matches!(
+            wrong.decrypt(&prod, &prod_ad),
+            Err(YorError::WrongPassword)
+        ));
+
+        // Another type, field or database
+        assert!(key
+            .decrypt(&prod, &AssociatedData::new(&db, "aws_prod", "data/str"))
+            .is_err());
+        assert!(key
+            .decrypt(&prod, &prod_ad.clone().field("password"))
+            .is_err());
+        let mut other = create_db("home", None);
+        set_db_id(&mut other).unwrap();
+        assert!(key
+            .decrypt(&prod, &AssociatedData::new(&other, "aws_prod", "data/byte"))
+            .is_err());
+
+        // The same database copied or renamed
+        let mut copy = create_db("work-copy", None);
+        copy.set(META_KEY, &get_meta(&db)).unwrap();
+        let copy_ad = AssociatedData::new(&copy, "aws_prod", "data/byte");
+        assert_eq!(key.decrypt(&prod, &copy_ad).unwrap(), b"prod-secret");
+
+        // The values written before are still read, the version is checked
+        let legacy = encrypt_with("legacy", "hunter2", &KdfParams::default()).unwrap();
+        assert!(is_outdated(&legacy));
+        assert_eq!(key.decrypt(&legacy, &prod_ad).unwrap(), b"legacy");
+        let mut downgraded = prod.clone();
+        downgraded[3] = CIPHER_VERSION;
+        assert!(key.decrypt(&downgraded, &prod_ad).is_err());
+    }
+
+    #[test]
+    fn touch_keeps_concurrent_writes() {
+        test_vault_root();
+        let path = get_db_path("touch_concurrent");
+        let mut writer = Vault::create(&path, None, false).unwrap();
+        writer
+            .set_value("token", b"one", "data/str", &Default::default())
+            .unwrap();
+
+        // A reader loads the database, then another command writes it
+        let mut reader = Vault::open(&path, None).unwrap();
+        writer
+            .set_value("other", b"two", "data/str", &Default::default())
+            .unwrap();
+        reader.touch(&["token"]).unwrap();
+
+        let vault = Vault::open(&path, None).unwrap();
+        assert_eq!(vault.get_string("other").unwrap(), "two");
+        assert!(vault.entry("token").unwrap().last_accessed.is_some());
+        assert!(get_all_db()
+            .unwrap()
+            .iter()
+            .all(|db| !db.name.starts_with('.')));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hotp_counter_overflow() {
+        test_vault_root();
+        let path = get_db_path("hotp_counter");
+        let mut vault = Vault::create(&path, Some("hunter2"), false).unwrap();
+        let mut otp = Otp::parse("JBSWY3DPEHPK3PXP").unwrap();
+        otp.counter = Some(u64::MAX);
+        assert!(matches!(
+            vault.set_otp("bank", &otp, &Default::default()),
+            Err(YorError::Invalid(_))
+        ));
+
+        // The last counter gives a code, the next one can't be saved
+        otp.counter = Some(u64::MAX - 1);
+        vault.set_otp("bank", &otp, &Default::default()).unwrap();
+        let (code, remaining) = vault.otp_code("bank").unwrap();
+        assert_eq!(code, otp.code(u64::MAX - 1));
+        assert_eq!(remaining, None);
+        assert!(matches!(vault.otp_code("bank"), Err(YorError::Invalid(_))));
+        assert_eq!(vault.otp("bank").unwrap().counter, Some(u64::MAX));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn derived_key_without_password() {
+        let db = create_db("work", None);
+        let ad = AssociatedData::new(&db, "token", "data/byte");
+        let key = DbKey::new("hunter2", &salt().unwrap(), KdfParams::default());
+        let ciphertext = key.encrypt("s3cr3t", &ad).unwrap();
+
+        // The key handed to the agent opens the values, not the password
+        let derived = key.derived_key().unwrap();
+        let agent_key = DbKey::from_derived(key.salt(), key.params(), &derived);
+        assert_eq!(agent_key.password(), None);
+        assert_eq!(agent_key.decrypt(&ciphertext, &ad).unwrap(), b"s3cr3t");
+        let reencrypted = agent_key.encrypt("s3cr3t", &ad).unwrap();
+        assert_eq!(key.decrypt(&reencrypted, &ad).unwrap(), b"s3cr3t");
+
+        // Nor the values of another salt or encrypted with the password
+        let other = DbKey::new("hunter2", &salt().unwrap(), KdfParams::default());
+        let other = other.encrypt("s3cr3t", &ad).unwrap();
+        assert!(matches!(
+            agent_key.decrypt(&other, &ad),
+            Err(YorError::WrongPassword)
+        ));
+        let legacy = encrypt_with("legacy", "hunter2", &KdfParams::default()).unwrap();
+        assert!(agent_key.decrypt(&legacy, &ad).is_err());
+    }
+
+    #[test]
+    fn parse_ttl_units() {
+        assert_eq!(parse_ttl("30s").unwrap(), 30);
+        assert_eq!(parse_ttl("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_ttl("1h30m").unwrap(), 90 * 60);
+        assert_eq!(parse_ttl(" 7d ").unwrap(), 7 * 24 * 60 * 60);
+        assert_eq!(parse_ttl("2w1d").unwrap(), 15 * 24 * 60 * 60);
+        for ttl in [
+            "", "30", "0m", "h", "1h30", "5y", "1.5h", "-1h", "1 h", "1H",
+        ] {
+            assert!(parse_ttl(ttl).is_err(), "{}", ttl);
+        }
+        // Neither the number nor the sum may overflow
+        assert!(parse_ttl("99999999999999999999s").is_err());
+        assert!(parse_ttl(&format!("{}w", i64::MAX / 60)).is_err());
+        assert!(parse_ttl(&format!("{}s1s", i64::MAX)).is_err());
+        assert_eq!(parse_ttl(&format!("{}s", i64::MAX)).unwrap(), i64::MAX);
+    }
+
+    #[test]
+    fn parse_date_formats() {
+        use chrono::{Local, NaiveDate, TimeZone};
+
+        assert_eq!(parse_date("2026-10-16T18:00:00Z").unwrap(), 1792173600);
+        assert_eq!(parse_date("2026-10-16T20:00:00+02:00").unwrap(), 1792173600);
+        let local = |h, m| {
+            let time = NaiveDate::from_ymd_opt(2026, 10, 16)
+                .and_then(|d| d.and_hms_opt(h, m, 0))
+                .unwrap();
+            Local
+                .from_local_datetime(&time)
+                .earliest()
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(parse_date("2026-10-16 18:30").unwrap(), local(18, 30));
+        assert_eq!(parse_date("2026-10-16T18:30").unwrap(), local(18, 30));
+        assert_eq!(parse_date("2026-10-16").unwrap(), local(0, 0));
+        for date in [
+            "",
+            "tomorrow",
+            "16/10/2026",
+            "2026-13-01",
+            "2026-02-30",
+            "2026-10-16 25:00",
+            "2026-10-16 18",
+            "1792173600",
+        ] {
+            assert!(parse_date(date).is_err(), "{}", date);
+        }
     }
-    db.rem(&key)?;
-    Ok(())
 }